@@ -19,7 +19,10 @@ in pkgs.mkShell {
     xorg.libX11
     sqlite
     libpulseaudio
+    pipewire
   ];
 
+  # bindgen for the optional `pipewire` feature
+  LIBCLANG_PATH = "${pkgs.libclang.lib}/lib";
   RUST_SRC_PATH = "${rustPackages.rustPlatform.rustLibSrc}";
 }
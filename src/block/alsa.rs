@@ -0,0 +1,383 @@
+use std::cell::RefCell;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+
+const SNDRV_CTL_ELEM_IFACE_MIXER: i32 = 2;
+const SNDRV_CTL_ELEM_TYPE_BOOLEAN: i32 = 1;
+const SNDRV_CTL_ELEM_TYPE_INTEGER: i32 = 2;
+
+const fn iowr(nr: u32, size: usize) -> u32 {
+    #[allow(clippy::cast_possible_truncation)]
+    let size = size as u32;
+    (3 << 30) | (size << 16) | ((b'U' as u32) << 8) | nr
+}
+
+const SNDRV_CTL_IOCTL_ELEM_INFO: u32 = iowr(0x11, size_of::<ElemInfo>());
+const SNDRV_CTL_IOCTL_ELEM_READ: u32 = iowr(0x12, size_of::<ElemValue>());
+const SNDRV_CTL_IOCTL_ELEM_WRITE: u32 = iowr(0x13, size_of::<ElemValue>());
+const SNDRV_CTL_IOCTL_SUBSCRIBE_EVENTS: u32 = iowr(0x16, size_of::<libc::c_int>());
+
+const DEFAULT_CONTROL: &str = "Master";
+const DEFAULT_STEP: u32 = 5;
+
+/// `struct snd_ctl_elem_id`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElemId {
+    numid: u32,
+    iface: i32,
+    device: u32,
+    subdevice: u32,
+    name: [u8; 44],
+    index: u32,
+}
+
+/// `struct snd_ctl_elem_info`, `value` is a union whose integer variant is `min, max, step`
+#[repr(C)]
+struct ElemInfo {
+    id: ElemId,
+    ty: i32,
+    access: u32,
+    count: u32,
+    owner: libc::pid_t,
+    value: [libc::c_long; 128 / size_of::<libc::c_long>()],
+    reserved: [u8; 64],
+}
+
+/// `struct snd_ctl_elem_value`, only the integer variant of `value` is used
+#[repr(C)]
+struct ElemValue {
+    id: ElemId,
+    indirect: u32,
+    value: [libc::c_long; 128],
+    reserved: [u8; 128],
+}
+
+/// One control element of a card, e.g. `Master Playback Volume`
+#[derive(Clone, Copy)]
+struct Elem {
+    id: ElemId,
+    count: usize,
+    min: libc::c_long,
+    max: libc::c_long,
+}
+
+/// The control device of a card, `/dev/snd/controlC<card>`
+struct Ctl {
+    fd: OwnedFd,
+    volume: Elem,
+    switch: Option<Elem>,
+    // eventfd telling the thread watching the card to stop
+    stop: Option<OwnedFd>,
+}
+
+impl Drop for Ctl {
+    fn drop(&mut self) {
+        if let Some(stop) = &self.stop {
+            let one = 1u64;
+            unsafe { libc::write(stop.as_raw_fd(), (&raw const one).cast(), size_of::<u64>()) };
+        }
+    }
+}
+
+impl Ctl {
+    fn open(card: u32, control: &str) -> Result<Self, anyhow::Error> {
+        let path = std::ffi::CString::new(format!("/dev/snd/controlC{card}"))?;
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let volume = elem(&fd, &format!("{control} Playback Volume"))?;
+        if volume.1 != SNDRV_CTL_ELEM_TYPE_INTEGER {
+            return Err(anyhow::anyhow!("{control} has no integer volume"));
+        }
+        // not every control can be muted
+        let switch = elem(&fd, &format!("{control} Playback Switch"))
+            .ok()
+            .filter(|e| e.1 == SNDRV_CTL_ELEM_TYPE_BOOLEAN);
+
+        Ok(Self {
+            fd,
+            volume: volume.0,
+            switch: switch.map(|e| e.0),
+            stop: None,
+        })
+    }
+
+    fn read(&self, elem: &Elem) -> Result<Vec<libc::c_long>, anyhow::Error> {
+        let mut value = ElemValue {
+            id: elem.id,
+            indirect: 0,
+            value: [0; 128],
+            reserved: [0; 128],
+        };
+        ioctl(&self.fd, SNDRV_CTL_IOCTL_ELEM_READ, &raw mut value)?;
+        Ok(value.value[..elem.count.min(128)].to_vec())
+    }
+
+    fn write(&self, elem: &Elem, values: &[libc::c_long]) -> Result<(), anyhow::Error> {
+        let mut value = ElemValue {
+            id: elem.id,
+            indirect: 0,
+            value: [0; 128],
+            reserved: [0; 128],
+        };
+        for (d, s) in value.value.iter_mut().zip(values) {
+            *d = *s;
+        }
+        ioctl(&self.fd, SNDRV_CTL_IOCTL_ELEM_WRITE, &raw mut value)
+    }
+
+    /// Average volume in percent of the control's range, and whether it is muted
+    fn state(&self) -> Result<(u32, bool), anyhow::Error> {
+        let values = self.read(&self.volume)?;
+        let range = (self.volume.max - self.volume.min).max(1);
+        let sum: libc::c_long = values.iter().map(|v| v - self.volume.min).sum();
+        let count = libc::c_long::try_from(values.len().max(1))?;
+        let volume = u32::try_from((sum * 100 / range + count / 2) / count)?;
+
+        // muted when every channel is switched off
+        let mute = match &self.switch {
+            Some(switch) => self.read(switch)?.iter().all(|v| *v == 0),
+            None => false,
+        };
+        Ok((volume, mute))
+    }
+}
+
+fn ioctl<T>(fd: &OwnedFd, request: u32, arg: *mut T) -> Result<(), anyhow::Error> {
+    let r = unsafe { libc::ioctl(fd.as_raw_fd(), libc::Ioctl::from(request), arg) };
+    if r < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Looks up a mixer element by name, returning it with its type
+fn elem(fd: &OwnedFd, name: &str) -> Result<(Elem, i32), anyhow::Error> {
+    let mut id = ElemId {
+        numid: 0,
+        iface: SNDRV_CTL_ELEM_IFACE_MIXER,
+        device: 0,
+        subdevice: 0,
+        name: [0; 44],
+        index: 0,
+    };
+    for (d, s) in id.name.iter_mut().zip(name.bytes().take(43)) {
+        *d = s;
+    }
+
+    let mut info = ElemInfo {
+        id,
+        ty: 0,
+        access: 0,
+        count: 0,
+        owner: 0,
+        value: [0; 128 / size_of::<libc::c_long>()],
+        reserved: [0; 64],
+    };
+    ioctl(fd, SNDRV_CTL_IOCTL_ELEM_INFO, &raw mut info)?;
+
+    Ok((
+        Elem {
+            // the kernel filled in the numid, which is cheaper to look up
+            id: info.id,
+            count: usize::try_from(info.count)?,
+            min: info.value[0],
+            max: info.value[1],
+        },
+        info.ty,
+    ))
+}
+
+/// Waits for events on the control device and redraws, until the card goes away or `ctl` is
+/// dropped
+fn watch(ctl: &mut Ctl, gone: &Arc<AtomicBool>, redraw: mpsc::Sender<()>) {
+    let subscribed = ctl
+        .fd
+        .try_clone()
+        .map_err(anyhow::Error::from)
+        .and_then(|fd| {
+            let mut on: libc::c_int = 1;
+            ioctl(&fd, SNDRV_CTL_IOCTL_SUBSCRIBE_EVENTS, &raw mut on).map(|()| fd)
+        })
+        .and_then(|fd| {
+            let stop = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+            if stop < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok((fd, unsafe { OwnedFd::from_raw_fd(stop) }))
+        });
+    let (fd, stop) = match subscribed {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("alsa events not available, polling instead: {e}");
+            return;
+        }
+    };
+    let Ok(stopped) = stop.try_clone() else {
+        return;
+    };
+    ctl.stop = Some(stop);
+
+    let gone = gone.clone();
+    std::thread::spawn(move || {
+        // `struct snd_ctl_event`, we do not care which element changed
+        let mut buf = [0u8; 72 * 16];
+        loop {
+            let mut pfds = [
+                libc::pollfd {
+                    fd: fd.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: stopped.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            let r = unsafe { libc::poll(pfds.as_mut_ptr(), 2, -1) };
+            if r < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            // the card was closed, whoever did that opens it again if needed
+            if pfds[1].revents != 0 {
+                return;
+            }
+            // the card went away
+            if r < 0 || pfds[0].revents & (libc::POLLERR | libc::POLLHUP) != 0 {
+                break;
+            }
+            let r = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if r <= 0 {
+                break;
+            }
+            let _ = redraw.send(());
+        }
+        gone.store(true, Ordering::Relaxed);
+        let _ = redraw.send(());
+    });
+}
+
+pub struct Alsa {
+    card: u32,
+    control: String,
+    step: u32,
+    ctl: RefCell<Option<Ctl>>,
+    gone: Arc<AtomicBool>,
+    redraw: mpsc::Sender<()>,
+}
+
+impl Alsa {
+    pub fn new(redraw: mpsc::Sender<()>) -> Self {
+        Self {
+            card: 0,
+            control: DEFAULT_CONTROL.into(),
+            step: DEFAULT_STEP,
+            ctl: RefCell::new(None),
+            gone: Arc::new(AtomicBool::new(false)),
+            redraw,
+        }
+    }
+
+    /// Index of the card, as in `/dev/snd/controlC<card>`
+    pub const fn card(mut self, card: u32) -> Self {
+        self.card = card;
+        self
+    }
+
+    /// Simple mixer control to show, e.g. `Master`, `PCM` or `Speaker`
+    pub fn control(mut self, control: &str) -> Self {
+        self.control = control.into();
+        self
+    }
+
+    /// Percentage `up` and `down` change the volume by if no amount is given
+    pub const fn step(mut self, step: u32) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Opens the card again if it went away, e.g. an unplugged USB sound card
+    fn reopen(&self) -> Result<(), anyhow::Error> {
+        if self.gone.swap(false, Ordering::Relaxed) {
+            self.ctl.borrow_mut().take();
+        }
+        if self.ctl.borrow().is_some() {
+            return Ok(());
+        }
+        let mut ctl = Ctl::open(self.card, &self.control)?;
+        watch(&mut ctl, &self.gone, self.redraw.clone());
+        *self.ctl.borrow_mut() = Some(ctl);
+        Ok(())
+    }
+
+    /// Closes the card and stops watching it, e.g. once a sound server is back
+    pub(super) fn close(&self) {
+        self.ctl.borrow_mut().take();
+    }
+
+    /// `up [n]|down [n]|set <n>|mute`, the same as for `pulse`. `name` is the block the command
+    /// was sent to.
+    pub(super) fn control_command(&self, name: &str, args: &[&str]) -> Result<(), anyhow::Error> {
+        self.reopen()?;
+        let ctl = self.ctl.borrow();
+        let Some(ctl) = ctl.as_ref() else {
+            return Err(anyhow::anyhow!("not connected"));
+        };
+
+        let (volume, mute) = ctl.state()?;
+        let volume = match args {
+            ["up"] => volume.saturating_add(self.step),
+            ["up", n] => volume.saturating_add(n.parse()?),
+            ["down"] => volume.saturating_sub(self.step),
+            ["down", n] => volume.saturating_sub(n.parse()?),
+            ["set", n] => n.parse()?,
+            ["mute"] => {
+                let Some(switch) = &ctl.switch else {
+                    return Err(anyhow::anyhow!("{} can not be muted", self.control));
+                };
+                // the switch is on when the channel plays, so unmuting turns it on
+                return ctl.write(switch, &vec![libc::c_long::from(mute); switch.count]);
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "usage: {name} up [n]|down [n]|set <n>|mute"
+                ));
+            }
+        };
+
+        let range = ctl.volume.max - ctl.volume.min;
+        let raw = ctl.volume.min + range * libc::c_long::from(volume.min(100)) / 100;
+        ctl.write(&ctl.volume, &vec![raw; ctl.volume.count])
+    }
+}
+
+impl super::Block for Alsa {
+    fn command(&self, args: &[&str]) -> Option<Result<(), anyhow::Error>> {
+        let ["alsa", args @ ..] = args else {
+            return None;
+        };
+        Some(self.control_command("alsa", args))
+    }
+
+    fn run(&self) -> Result<Option<String>, anyhow::Error> {
+        if self.reopen().is_err() {
+            return Ok(Some("🔈 ✖".into()));
+        }
+        let state = self.ctl.borrow().as_ref().map(Ctl::state);
+        // most likely the card went away between two events
+        let Some(Ok((volume, mute))) = state else {
+            self.ctl.borrow_mut().take();
+            return Ok(Some("🔈 ✖".into()));
+        };
+        if mute {
+            return Ok(Some("🔇".into()));
+        }
+        Ok(Some(format!("{} {volume}%", super::pulse::speaker(volume))))
+    }
+}
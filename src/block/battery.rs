@@ -1,75 +1,537 @@
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
 
-pub struct Battery {
+use crate::netlink;
+use crate::notify::{Notifier, Notify, Urgency};
+
+// how long the charge/discharge rate is averaged over, however often we are run
+const WINDOW: Duration = Duration::from_secs(30);
+// with uevents plug/unplug is instant, capacity changes are only picked up every POLL_INTERVAL
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Properties of a power supply as listed in its `uevent` file, keyed by their lowercase name
+/// without the `POWER_SUPPLY_` prefix, e.g. `energy_now`
+pub(super) struct Props(HashMap<String, String>);
+
+impl Props {
+    pub(super) fn read(dir: &Path) -> Result<Self, anyhow::Error> {
+        Ok(Self(
+            std::fs::read_to_string(dir.join("uevent"))?
+                .lines()
+                .filter_map(|l| {
+                    let (k, v) = l.split_once('=')?;
+                    Some((
+                        k.strip_prefix("POWER_SUPPLY_")?.to_lowercase(),
+                        v.trim().to_string(),
+                    ))
+                })
+                .collect(),
+        ))
+    }
+
+    pub(super) fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    fn num(&self, key: &str) -> Option<f64> {
+        self.get(key)?.parse::<f64>().ok()
+    }
+
+    fn status(&self) -> &str {
+        self.get("status").unwrap_or("Unknown")
+    }
+
+    /// Current and full amount, either in µWh or µAh, whatever the driver exposes
+    fn amounts(&self) -> Option<(f64, f64)> {
+        self.num("energy_now")
+            .zip(self.num("energy_full"))
+            .or_else(|| self.num("charge_now").zip(self.num("charge_full")))
+    }
+
+    /// Current and full energy in µWh and the power in µW, converted from charge and current if
+    /// the driver only exposes those, so packs can be summed up
+    fn levels(&self) -> Option<(f64, f64, f64)> {
+        if let (Some(now), Some(full), Some(rate)) = (
+            self.num("energy_now"),
+            self.num("energy_full"),
+            self.num("power_now"),
+        ) {
+            return Some((now, full, rate.abs()));
+        }
+        let voltage = self.num("voltage_now")? / 1_000_000.0;
+        Some((
+            self.num("charge_now")? * voltage,
+            self.num("charge_full")? * voltage,
+            self.num("current_now")?.abs() * voltage,
+        ))
+    }
+
+    /// Percentage, computed from the amounts if there is no `capacity`
+    pub(super) fn capacity(&self) -> Option<f64> {
+        self.num("capacity").or_else(|| {
+            let (now, full) = self.amounts()?;
+            (full > 0.0).then(|| (now / full * 100.0).clamp(0.0, 100.0))
+        })
+    }
+
+    /// Full and design capacity, either in µWh or µAh
+    fn wear(&self) -> Option<(f64, f64)> {
+        self.num("energy_full")
+            .zip(self.num("energy_full_design"))
+            .or_else(|| self.num("charge_full").zip(self.num("charge_full_design")))
+            .filter(|(_, design)| *design > 0.0)
+    }
+
+    /// Power draw in W
+    fn watt(&self) -> Option<f64> {
+        self.num("power_now")
+            .map(|p| p.abs() / 1_000_000.0)
+            .or_else(|| {
+                let (current, voltage) = self.num("current_now").zip(self.num("voltage_now"))?;
+                Some((current * voltage).abs() / 1_000_000_000_000.0)
+            })
+    }
+}
+
+fn format_hours(hours: f64) -> String {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let minutes = (hours * 60.0).round() as u64;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+#[derive(Default)]
+struct Window {
+    status: String,
+    rates: VecDeque<(Instant, f64)>,
+}
+
+impl Window {
+    /// Adds a sample and returns the average rate over the last `WINDOW`, starting over if the
+    /// status changed
+    fn push(&mut self, status: &str, rate: f64) -> f64 {
+        if self.status != status {
+            self.status = status.to_string();
+            self.rates.clear();
+        }
+        let now = Instant::now();
+        while self
+            .rates
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > WINDOW)
+        {
+            self.rates.pop_front();
+        }
+        self.rates.push_back((now, rate));
+        #[allow(clippy::cast_precision_loss)]
+        let avg = self.rates.iter().map(|(_, r)| r).sum::<f64>() / self.rates.len() as f64;
+        avg
+    }
+}
+
+struct Reading {
+    cap: f64,
+    status: String,
+    watt: Option<f64>,
+    levels: Option<(f64, f64, f64)>,
+
+    wear: Option<(f64, f64)>,
+    cycles: Option<u32>,
+    limit: Option<u8>,
+}
+
+impl Reading {
+    /// `mains` tells whether an adapter is plugged in, if there is one, which is used to
+    /// correct an `Unknown` status
+    fn read(bat: &Path, mains: Option<bool>) -> Result<Option<Self>, anyhow::Error> {
+        let props = Props::read(bat)?;
+        let Some(cap) = props.capacity() else {
+            return Ok(None);
+        };
+        let status = match (props.status(), mains) {
+            ("Unknown", Some(true)) => "Not charging",
+            ("Unknown", Some(false)) => "Discharging",
+            (status, _) => status,
+        };
+        Ok(Some(Self {
+            cap,
+            status: status.to_string(),
+            watt: props.watt(),
+            levels: props.levels(),
+
+            wear: props.wear(),
+            cycles: props
+                .get("cycle_count")
+                .and_then(|v| v.parse::<u32>().ok())
+                .filter(|v| *v > 0),
+            limit: std::fs::read_to_string(bat.join("charge_control_end_threshold"))
+                .ok()
+                .and_then(|v| v.trim().parse::<u8>().ok())
+                .filter(|v| *v < 100),
+        }))
+    }
+
+    fn health(&self) -> Vec<String> {
+        let mut out = vec![];
+        if let Some((full, design)) = self.wear {
+            out.push(format!("♥{:.0}%", full / design * 100.0));
+        }
+        if let Some(cycles) = self.cycles {
+            out.push(format!("↻{cycles}"));
+        }
+        if let Some(limit) = self.limit {
+            out.push(format!("≤{limit}%"));
+        }
+        out
+    }
+
+    /// Combines all packs into one, weighting the percentage by their energy if possible
+    fn combine(readings: &[Self]) -> Option<Self> {
+        if readings.is_empty() {
+            return None;
+        }
+
+        let levels = readings
+            .iter()
+            .map(|r| r.levels)
+            .try_fold((0.0, 0.0, 0.0), |acc, l| {
+                let (now, full, rate) = l?;
+                Some((acc.0 + now, acc.1 + full, acc.2 + rate))
+            });
+        #[allow(clippy::cast_precision_loss)]
+        let cap = match levels {
+            Some((now, full, _)) if full > 0.0 => (now / full * 100.0).clamp(0.0, 100.0),
+            _ => readings.iter().map(|r| r.cap).sum::<f64>() / readings.len() as f64,
+        };
+        let status = ["Discharging", "Charging", "Not charging"]
+            .into_iter()
+            .find(|s| readings.iter().any(|r| r.status == *s))
+            .or_else(|| {
+                readings
+                    .iter()
+                    .all(|r| r.status == "Full")
+                    .then_some("Full")
+            })
+            .unwrap_or("Unknown");
+        let watt = readings.iter().filter_map(|r| r.watt).reduce(|a, b| a + b);
+        let wear = readings
+            .iter()
+            .map(|r| r.wear)
+            .try_fold((0.0, 0.0), |acc, w| {
+                let (full, design) = w?;
+                Some((acc.0 + full, acc.1 + design))
+            });
+
+        Some(Self {
+            cap,
+            status: status.to_string(),
+            watt,
+            levels,
+
+            wear,
+            cycles: readings.iter().filter_map(|r| r.cycles).max(),
+            limit: readings.iter().filter_map(|r| r.limit).min(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct Supplies {
     batteries: Vec<PathBuf>,
+    adapters: Vec<PathBuf>,
 }
 
-impl Battery {
-    pub fn new() -> Self {
-        let mut batteries = vec![];
+impl Supplies {
+    fn scan() -> Self {
+        let mut s = Self::default();
         if let Ok(dir) = std::fs::read_dir("/sys/class/power_supply") {
             for ps in dir.flatten() {
                 if ps
                     .file_name()
                     .into_string()
-                    .map(|x| x.starts_with("BAT"))
-                    .unwrap_or(false)
+                    .is_ok_and(|x| x.starts_with("BAT"))
                 {
-                    batteries.push(ps.path());
+                    s.batteries.push(ps.path());
+                } else if Props::read(&ps.path()).is_ok_and(|p| p.get("type") == Some("Mains")) {
+                    s.adapters.push(ps.path());
                 }
             }
         }
+        s.batteries.sort();
+        s
+    }
 
-        Self { batteries }
+    /// Whether any adapter is online, `None` if there is no adapter at all
+    fn mains(&self) -> Option<bool> {
+        if self.adapters.is_empty() {
+            return None;
+        }
+        Some(
+            self.adapters
+                .iter()
+                .any(|a| Props::read(a).is_ok_and(|p| p.get("online") == Some("1"))),
+        )
     }
 }
 
-impl super::Block for Battery {
-    fn run(&self) -> Result<Option<String>, anyhow::Error> {
-        if self.batteries.is_empty() {
-            return Ok(None);
+/// Marks the supplies as dirty on `power_supply` uevents, and to be scanned again when one
+/// comes or goes
+fn watch(
+    watching: &Arc<AtomicBool>,
+    dirty: &Arc<AtomicBool>,
+    rescan: &Arc<AtomicBool>,
+    redraw: mpsc::Sender<()>,
+) {
+    let dirty = dirty.clone();
+    let rescan = rescan.clone();
+    netlink::listen(
+        libc::NETLINK_KOBJECT_UEVENT,
+        1,
+        "uevents",
+        watching,
+        redraw,
+        move |buf| {
+            match buf {
+                Some(buf) => {
+                    // `action@devpath` followed by `KEY=value`, all nul separated
+                    let mut fields = buf.split(|b| *b == 0);
+                    let action = fields
+                        .next()
+                        .and_then(|v| v.split(|b| *b == b'@').next())
+                        .unwrap_or_default();
+                    if !fields.any(|f| f == b"SUBSYSTEM=power_supply") {
+                        return false;
+                    }
+                    if action == b"add" || action == b"remove" {
+                        rescan.store(true, Ordering::Relaxed);
+                    }
+                }
+                // we missed events, just refresh everything
+                None => rescan.store(true, Ordering::Relaxed),
+            }
+            dirty.store(true, Ordering::Relaxed);
+            true
+        },
+    );
+}
+
+pub struct Battery {
+    supplies: RefCell<Supplies>,
+    windows: RefCell<HashMap<String, Window>>,
+
+    aggregate: bool,
+    detail: bool,
+    health: bool,
+
+    thresholds: Vec<u8>,
+    command: Option<(u8, Vec<String>)>,
+    notifier: Box<dyn Notify>,
+    // lowest threshold we already notified about since the last time we were charging
+    notified: Cell<Option<u8>>,
+    command_ran: Cell<bool>,
+
+    watching: Arc<AtomicBool>,
+    dirty: Arc<AtomicBool>,
+    rescan: Arc<AtomicBool>,
+    polled: Cell<Option<Instant>>,
+    last: RefCell<Option<String>>,
+}
+
+impl Battery {
+    pub fn new(redraw: mpsc::Sender<()>) -> Self {
+        let watching = Arc::new(AtomicBool::new(false));
+        let dirty = Arc::new(AtomicBool::new(true));
+        let rescan = Arc::new(AtomicBool::new(false));
+        watch(&watching, &dirty, &rescan, redraw);
+
+        Self {
+            supplies: RefCell::new(Supplies::scan()),
+            windows: RefCell::new(HashMap::new()),
+
+            aggregate: false,
+            detail: false,
+            health: false,
+
+            thresholds: vec![],
+            command: None,
+            notifier: Box::new(Notifier::new()),
+            notified: Cell::new(None),
+            command_ran: Cell::new(false),
+
+            watching,
+            dirty,
+            rescan,
+            polled: Cell::new(None),
+            last: RefCell::new(None),
         }
+    }
 
-        let mut out: Vec<String> = vec![];
-        for bat in &self.batteries {
-            let cap = std::fs::read_to_string(bat.join("capacity"))
-                .ok()
-                .and_then(|v| v.trim().replace('$', "").parse::<i32>().ok());
-            let Some(cap) = cap else { continue };
-            let sep = if cap < 25 { "❗" } else { " " };
+    /// Also shows the health (full vs design capacity), cycle count and charge limit
+    pub const fn health(mut self, health: bool) -> Self {
+        self.health = health;
+        self
+    }
 
-            let current = std::fs::read_to_string(bat.join("current_now"))
-                .ok()
-                .and_then(|v| v.trim().parse::<f64>().ok());
-            let voltage = std::fs::read_to_string(bat.join("voltage_now"))
-                .ok()
-                .and_then(|v| v.trim().parse::<f64>().ok());
-            let mut watt = if let (Some(current), Some(voltage)) = (current, voltage) {
-                Some((current * voltage) / 1_000_000_000_000.0)
-            } else {
-                None
-            };
-
-            let status = match std::fs::read_to_string(bat.join("status"))?
-                .trim()
-                .replace(',', "")
-                .as_str()
-            {
-                "Discharging" => "🔋".into(),
-                "Charging" | "Not charging" => {
-                    watt = None; // dont show watt if we are currently charging
-                    "🔌".into()
+    /// Sends a desktop notification once when discharging below each of these percentages, the
+    /// lowest one with critical urgency
+    pub fn notify(mut self, thresholds: &[u8]) -> Self {
+        self.thresholds = thresholds.to_vec();
+        self
+    }
+
+    /// Runs `command` once when discharging below `threshold`, e.g. `systemctl suspend`
+    pub fn command_below(mut self, threshold: u8, command: &[&str]) -> Self {
+        self.command = Some((threshold, command.iter().map(ToString::to_string).collect()));
+        self
+    }
+
+    fn alert(&self, reading: &Reading) {
+        if reading.status != "Discharging" {
+            self.notified.set(None);
+            self.command_ran.set(false);
+            return;
+        }
+
+        let crossed = self
+            .thresholds
+            .iter()
+            .copied()
+            .filter(|t| reading.cap <= f64::from(*t))
+            .min();
+        match crossed {
+            Some(t) if self.notified.get().is_none_or(|n| t < n) => {
+                self.notified.set(Some(t));
+                let urgency = if self.thresholds.iter().min() == Some(&t) {
+                    Urgency::Critical
+                } else {
+                    Urgency::Normal
+                };
+                if let Err(e) = self.notifier.send(
+                    &format!("Battery below {t}%"),
+                    &format!("{:.0}% remaining", reading.cap),
+                    urgency,
+                ) {
+                    eprintln!("failed to send battery notification: {e}");
                 }
-                "Unknown" => "♻️".into(),
-                "Full" => "⚡".into(),
-                o => o.to_string(),
-            };
-            if let Some(watt) = watt {
-                out.push(format!("{status}{sep}{cap}% ({watt:.2}W)"));
-            } else {
-                out.push(format!("{status}{sep}{cap}%"));
             }
+            Some(_) => (),
+            None => self.notified.set(None),
+        }
+
+        if let Some((threshold, command)) = &self.command
+            && reading.cap <= f64::from(*threshold)
+            && !self.command_ran.get()
+            && let Some((program, args)) = command.split_first()
+        {
+            self.command_ran.set(true);
+            if let Err(e) = std::process::Command::new(program).args(args).spawn() {
+                eprintln!("failed to run {program}: {e}");
+            }
+        }
+    }
+
+    /// Shows all batteries as a single one
+    pub const fn aggregate(mut self) -> Self {
+        self.aggregate = true;
+        self
+    }
+
+    /// Appends the percentage of each pack when they are aggregated, e.g. `[80% 40%]`
+    pub const fn detail(mut self, detail: bool) -> Self {
+        self.detail = detail;
+        self
+    }
+
+    fn render(&self, key: &str, reading: &Reading) -> String {
+        #[allow(clippy::cast_possible_truncation)]
+        let cap = reading.cap.round() as i32;
+        let sep = if cap < 25 { "❗" } else { " " };
+        let mut watt = reading.watt;
+        let status = reading.status.as_str();
+
+        // time until empty while discharging or until full while charging
+        let remaining = reading.levels.and_then(|(now, full, rate)| {
+            let rate = self
+                .windows
+                .borrow_mut()
+                .entry(key.to_string())
+                .or_default()
+                .push(status, rate);
+            if rate <= 0.0 {
+                return None;
+            }
+            match status {
+                "Discharging" => Some(now / rate),
+                "Charging" => Some((full - now).max(0.0) / rate),
+                _ => None,
+            }
+        });
+
+        let status = match status {
+            "Discharging" => "🔋".into(),
+            "Charging" | "Not charging" => {
+                watt = None; // dont show watt if we are currently charging
+                "🔌".into()
+            }
+            "Unknown" => "♻️".into(),
+            "Full" => "⚡".into(),
+            o => o.to_string(),
+        };
+        let mut text = vec![format!("{status}{sep}{cap}%")];
+        if let Some(watt) = watt {
+            text.push(format!("({watt:.2}W)"));
         }
+        if let Some(remaining) = remaining {
+            text.push(format_hours(remaining));
+        }
+        if self.health {
+            text.extend(reading.health());
+        }
+        text.join(" ")
+    }
+
+    fn read(&self) -> Result<Option<String>, anyhow::Error> {
+        let supplies = self.supplies.borrow();
+        if supplies.batteries.is_empty() {
+            return Ok(None);
+        }
+
+        let mains = supplies.mains();
+        let mut keys = vec![];
+        let mut all = vec![];
+        for bat in &supplies.batteries {
+            if let Some(r) = Reading::read(bat, mains)? {
+                keys.push(bat.to_string_lossy().into_owned());
+                all.push(r);
+            }
+        }
+
+        let Some(combined) = Reading::combine(&all) else {
+            return Ok(None);
+        };
+        self.alert(&combined);
+
+        let out = if self.aggregate {
+            let mut text = self.render("all", &combined);
+            if self.detail && all.len() > 1 {
+                #[allow(clippy::cast_possible_truncation)]
+                let packs = all
+                    .iter()
+                    .map(|r| format!("{}%", r.cap.round() as i32))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                text = format!("{text} [{packs}]");
+            }
+            vec![text]
+        } else {
+            keys.iter()
+                .zip(&all)
+                .map(|(key, r)| self.render(key, r))
+                .collect()
+        };
 
         if out.is_empty() {
             Ok(None)
@@ -78,3 +540,174 @@ impl super::Block for Battery {
         }
     }
 }
+
+impl super::Block for Battery {
+    fn run(&self) -> Result<Option<String>, anyhow::Error> {
+        if self.rescan.swap(false, Ordering::Relaxed) {
+            *self.supplies.borrow_mut() = Supplies::scan();
+        }
+
+        // other blocks wake us up as well, so count time rather than runs
+        let due = self
+            .polled
+            .get()
+            .is_none_or(|t| t.elapsed() >= POLL_INTERVAL);
+        if self.watching.load(Ordering::Relaxed)
+            && !self.dirty.swap(false, Ordering::Relaxed)
+            && !due
+        {
+            return Ok(self.last.borrow().clone());
+        }
+        self.polled.set(Some(Instant::now()));
+
+        let out = self.read();
+        if let Ok(v) = &out {
+            self.last.borrow_mut().clone_from(v);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn reading(cap: f64, status: &str, levels: Option<(f64, f64, f64)>) -> Reading {
+        Reading {
+            cap,
+            status: status.into(),
+            watt: levels.map(|(_, _, rate)| rate),
+            levels,
+            wear: None,
+            cycles: None,
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn combine_weights_by_energy() {
+        let combined = Reading::combine(&[
+            reading(100.0, "Full", Some((20.0, 20.0, 0.0))),
+            reading(10.0, "Discharging", Some((6.0, 60.0, 8.0))),
+        ])
+        .unwrap();
+        assert!((combined.cap - 32.5).abs() < 1e-9);
+        assert_eq!(combined.status, "Discharging");
+        assert_eq!(combined.levels, Some((26.0, 80.0, 8.0)));
+        assert_eq!(combined.watt, Some(8.0));
+    }
+
+    #[test]
+    fn combine_without_levels_averages() {
+        let combined = Reading::combine(&[
+            reading(80.0, "Full", None),
+            reading(40.0, "Full", Some((4.0, 10.0, 0.0))),
+        ])
+        .unwrap();
+        assert!((combined.cap - 60.0).abs() < 1e-9);
+        assert_eq!(combined.status, "Full");
+        assert!(combined.levels.is_none());
+    }
+
+    #[test]
+    fn combine_status() {
+        let combined =
+            Reading::combine(&[reading(50.0, "Unknown", None), reading(50.0, "Full", None)])
+                .unwrap();
+        assert_eq!(combined.status, "Unknown");
+        assert!(Reading::combine(&[]).is_none());
+    }
+
+    /// Remembers notifications instead of sending them
+    #[derive(Clone, Default)]
+    struct Sent(Rc<RefCell<Vec<(String, Urgency)>>>);
+
+    impl Notify for Sent {
+        fn send(&self, summary: &str, _: &str, urgency: Urgency) -> Result<(), anyhow::Error> {
+            self.0.borrow_mut().push((summary.to_string(), urgency));
+            Ok(())
+        }
+    }
+
+    impl Sent {
+        fn take(&self) -> Vec<(String, Urgency)> {
+            std::mem::take(&mut *self.0.borrow_mut())
+        }
+    }
+
+    fn alerting() -> (Battery, Sent) {
+        let (tx, _) = mpsc::channel();
+        let sent = Sent::default();
+        let mut battery = Battery::new(tx)
+            .notify(&[20, 10, 5])
+            .command_below(3, &["true"]);
+        battery.notifier = Box::new(sent.clone());
+        (battery, sent)
+    }
+
+    #[test]
+    fn alert_once_per_crossing() {
+        let (battery, sent) = alerting();
+        let below = |t| (format!("Battery below {t}%"), Urgency::Normal);
+
+        battery.alert(&reading(50.0, "Charging", None));
+        battery.alert(&reading(21.0, "Discharging", None));
+        assert_eq!(sent.take(), vec![]);
+
+        battery.alert(&reading(20.0, "Discharging", None));
+        battery.alert(&reading(19.0, "Discharging", None));
+        assert_eq!(sent.take(), vec![below(20)]);
+
+        battery.alert(&reading(9.0, "Discharging", None));
+        battery.alert(&reading(8.0, "Discharging", None));
+        assert_eq!(sent.take(), vec![below(10)]);
+
+        // the lowest one is critical
+        battery.alert(&reading(5.0, "Discharging", None));
+        assert_eq!(
+            sent.take(),
+            vec![("Battery below 5%".into(), Urgency::Critical)]
+        );
+    }
+
+    #[test]
+    fn alert_skips_thresholds_in_between() {
+        let (battery, sent) = alerting();
+        battery.alert(&reading(50.0, "Discharging", None));
+        battery.alert(&reading(7.0, "Discharging", None));
+        assert_eq!(
+            sent.take(),
+            vec![("Battery below 10%".into(), Urgency::Normal)]
+        );
+    }
+
+    #[test]
+    fn alert_resets_when_charging() {
+        let (battery, sent) = alerting();
+        battery.alert(&reading(15.0, "Discharging", None));
+        assert_eq!(sent.take().len(), 1);
+
+        battery.alert(&reading(16.0, "Charging", None));
+        battery.alert(&reading(15.0, "Discharging", None));
+        assert_eq!(
+            sent.take(),
+            vec![("Battery below 20%".into(), Urgency::Normal)]
+        );
+    }
+
+    #[test]
+    fn command_once_per_discharge() {
+        let (battery, _) = alerting();
+        battery.alert(&reading(4.0, "Discharging", None));
+        assert!(!battery.command_ran.get());
+
+        battery.alert(&reading(3.0, "Discharging", None));
+        assert!(battery.command_ran.get());
+        battery.alert(&reading(2.0, "Discharging", None));
+        assert!(battery.command_ran.get());
+
+        battery.alert(&reading(2.0, "Charging", None));
+        assert!(!battery.command_ran.get());
+    }
+}
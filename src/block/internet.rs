@@ -1,42 +1,547 @@
-pub struct Internet {}
+use std::cell::RefCell;
+use std::net::{Ipv4Addr, Ipv6Addr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, mpsc};
+use std::time::Duration;
+
+use crate::netlink;
+
+const DEFAULT_FORMAT: &str = "{icon} {ssid} {quality}%";
+
+const NL80211_CMD_GET_INTERFACE: u8 = 5;
+const NL80211_CMD_GET_STATION: u8 = 17;
+const NL80211_ATTR_IFINDEX: u16 = 3;
+const NL80211_ATTR_STA_INFO: u16 = 21;
+const NL80211_ATTR_SSID: u16 = 52;
+const NL80211_STA_INFO_SIGNAL: u16 = 7;
+const NL80211_STA_INFO_TX_BITRATE: u16 = 8;
+const NL80211_RATE_INFO_BITRATE: u16 = 1;
+const NL80211_RATE_INFO_BITRATE32: u16 = 5;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// `tun_flags` of a layer 3 tunnel, TAP devices like the ones of qemu are Ethernet
+const IFF_TUN: u32 = 0x0001;
+
+const SIOCGIWRANGE: u16 = 0x8B0B;
+// offset of `max_qual.qual` in `struct iw_range`
+const IW_RANGE_MAX_QUAL: usize = 44;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    Wireless,
+    Ethernet,
+    Tunnel,
+    Bridge,
+}
+
+impl Kind {
+    const fn icon(self) -> &'static str {
+        match self {
+            Self::Wireless => "📶",
+            Self::Ethernet => "🌐",
+            Self::Tunnel => "🔒",
+            Self::Bridge => "🌉",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Interface {
+    name: String,
+    index: u32,
+    kind: Kind,
+    up: bool,
+}
+
+/// `DEVTYPE` of the device from its `uevent` file, e.g. `wireguard`
+pub(super) fn devtype(dev: &Path) -> Option<String> {
+    let uevent = std::fs::read_to_string(dev.join("uevent")).ok()?;
+    uevent
+        .lines()
+        .find_map(|l| l.strip_prefix("DEVTYPE="))
+        .map(ToString::to_string)
+}
+
+pub(super) fn classify(dev: &Path) -> Option<Kind> {
+    let devtype = devtype(dev);
+    let devtype = devtype.as_deref();
+
+    if dev.join("wireless").exists() || dev.join("phy80211").exists() {
+        Some(Kind::Wireless)
+    } else if dev.join("bridge").exists() {
+        Some(Kind::Bridge)
+    } else if tun_flags(dev).is_some_and(|v| v & IFF_TUN != 0) || devtype == Some("wireguard") {
+        Some(Kind::Tunnel)
+    } else {
+        // ARPHRD_ETHER, everything else (loopback, sit, ...) is not interesting
+        match std::fs::read_to_string(dev.join("type")).ok()?.trim() {
+            "1" => Some(Kind::Ethernet),
+            _ => None,
+        }
+    }
+}
+
+pub(super) fn is_up(dev: &Path) -> Result<bool, anyhow::Error> {
+    // tunnels usually report `unknown` even if they are usable
+    Ok(matches!(
+        std::fs::read_to_string(dev.join("operstate"))?.trim(),
+        "up" | "unknown"
+    ))
+}
+
+fn tun_flags(dev: &Path) -> Option<u32> {
+    let flags = std::fs::read_to_string(dev.join("tun_flags")).ok()?;
+    u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).ok()
+}
+
+fn default_route() -> Option<String> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|l| {
+            let a = l.split_whitespace().collect::<Vec<_>>();
+            let (iface, dest, metric, mask) = (a.first()?, a.get(1)?, a.get(6)?, a.get(7)?);
+            if *dest != "00000000" || *mask != "00000000" {
+                return None;
+            }
+            Some((metric.parse::<u32>().ok()?, (*iface).to_string()))
+        })
+        .min()
+        .map(|(_, iface)| iface)
+}
+
+#[derive(Debug, Default)]
+struct WifiInfo {
+    ssid: Option<String>,
+    signal: Option<i8>,
+    // in 100kbit/s
+    bitrate: Option<u32>,
+}
+
+fn wifi_info(sock: &netlink::Socket, family: u16, index: u32) -> WifiInfo {
+    let mut attrs = vec![];
+    netlink::push_attr(&mut attrs, NL80211_ATTR_IFINDEX, &index.to_ne_bytes());
+
+    let mut info = WifiInfo::default();
+    if let Ok(msgs) = sock.genl_request(family, NL80211_CMD_GET_INTERFACE, 0, &attrs) {
+        info.ssid = msgs.iter().find_map(|m| {
+            netlink::attr(m.genl_attrs(), NL80211_ATTR_SSID)
+                .map(|v| String::from_utf8_lossy(v).into_owned())
+        });
+    }
+    if let Ok(msgs) =
+        sock.genl_request(family, NL80211_CMD_GET_STATION, netlink::NLM_F_DUMP, &attrs)
+        && let Some(sta) = msgs
+            .iter()
+            .find_map(|m| netlink::attr(m.genl_attrs(), NL80211_ATTR_STA_INFO))
+    {
+        info.signal = netlink::attr(sta, NL80211_STA_INFO_SIGNAL)
+            .and_then(|v| v.first())
+            .map(|v| i8::from_ne_bytes([*v]));
+        info.bitrate = netlink::attr(sta, NL80211_STA_INFO_TX_BITRATE).and_then(|rate| {
+            netlink::attr(rate, NL80211_RATE_INFO_BITRATE32)
+                .and_then(netlink::u32_attr)
+                .or_else(|| {
+                    netlink::attr(rate, NL80211_RATE_INFO_BITRATE)
+                        .and_then(netlink::u16_attr)
+                        .map(u32::from)
+                })
+        });
+    }
+    info
+}
+
+fn addresses(sock: &netlink::Socket, index: u32) -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
+    // struct ifaddrmsg with AF_UNSPEC to dump all families
+    let Ok(msgs) = sock.request(libc::RTM_GETADDR, netlink::NLM_F_DUMP, &[0; 8]) else {
+        return (None, None);
+    };
+
+    let (mut v4, mut v6) = (None, None);
+    for m in msgs.iter().filter(|m| m.ty == libc::RTM_NEWADDR) {
+        let Some(hdr) = m.payload.get(..8) else {
+            continue;
+        };
+        if netlink::u32_attr(&hdr[4..8]) != Some(index) || hdr[3] != libc::RT_SCOPE_UNIVERSE {
+            continue;
+        }
+        let attrs = &m.payload[8..];
+        match i32::from(hdr[0]) {
+            libc::AF_INET if v4.is_none() => {
+                v4 = netlink::attr(attrs, libc::IFA_LOCAL)
+                    .or_else(|| netlink::attr(attrs, libc::IFA_ADDRESS))
+                    .and_then(|v| <[u8; 4]>::try_from(v).ok())
+                    .map(Ipv4Addr::from);
+            }
+            libc::AF_INET6 if v6.is_none() => {
+                v6 = netlink::attr(attrs, libc::IFA_ADDRESS)
+                    .and_then(|v| <[u8; 16]>::try_from(v).ok())
+                    .map(Ipv6Addr::from);
+            }
+            _ => (),
+        }
+    }
+    (v4, v6)
+}
+
+/// Maximum link quality as reported by the driver through wireless extensions
+fn max_quality(id: &str) -> Option<u8> {
+    #[repr(C)]
+    struct IwPoint {
+        pointer: *mut libc::c_void,
+        length: u16,
+        flags: u16,
+    }
+    #[repr(C)]
+    struct IwReq {
+        name: [libc::c_char; libc::IFNAMSIZ],
+        data: IwPoint,
+    }
+
+    let mut range = [0u8; 2048];
+    let mut req = IwReq {
+        name: [0; libc::IFNAMSIZ],
+        data: IwPoint {
+            pointer: range.as_mut_ptr().cast(),
+            length: u16::try_from(range.len()).ok()?,
+            flags: 0,
+        },
+    };
+    for (d, s) in req.name.iter_mut().zip(id.bytes().take(libc::IFNAMSIZ - 1)) {
+        *d = libc::c_char::from_ne_bytes([s]);
+    }
+
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return None;
+    }
+    let r = unsafe { libc::ioctl(fd, libc::Ioctl::from(SIOCGIWRANGE), &raw mut req) };
+    unsafe { libc::close(fd) };
+    if r < 0 {
+        return None;
+    }
+    range.get(IW_RANGE_MAX_QUAL).copied().filter(|v| *v > 0)
+}
+
+fn link_quality(id: &str) -> Option<f32> {
+    std::fs::read_to_string("/proc/net/wireless")
+        .ok()?
+        .lines()
+        .find(|s| s.trim_start().starts_with(&format!("{id}:")))
+        .and_then(|v| v.split_whitespace().nth(2)?.parse::<f32>().ok())
+}
+
+/// Link quality in percent, scaled by the maximum the driver reports. Drivers without one get it
+/// from the signal strength instead, -50 dBm and above is perfect and -100 dBm and below
+/// unusable, the same scale `NetworkManager` uses.
+fn quality(id: &str, signal: Option<i8>) -> Option<i32> {
+    #[allow(clippy::cast_possible_truncation)]
+    if let Some((v, max)) = link_quality(id).zip(max_quality(id)) {
+        return Some((v * 100.0 / f32::from(max)) as i32);
+    }
+    signal.map(|v| (i32::from(v) + 100).clamp(0, 50) * 2)
+}
+
+/// The interface carrying the default route and its addresses, only changes on rtnetlink events
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Link {
+    iface: Option<Interface>,
+    ipv4: Option<Ipv4Addr>,
+    ipv6: Option<Ipv6Addr>,
+}
+
+#[allow(clippy::cast_sign_loss)]
+const RTNL_GROUPS: u32 = (libc::RTMGRP_LINK
+    | libc::RTMGRP_IPV4_IFADDR
+    | libc::RTMGRP_IPV6_IFADDR
+    | libc::RTMGRP_IPV4_ROUTE
+    | libc::RTMGRP_IPV6_ROUTE) as u32;
+
+/// Connectivity check that tells a working link apart from actually being online, e.g. behind a
+/// captive portal
+pub enum Probe {
+    /// `host:port` that has to accept a TCP connection
+    Tcp(String),
+    /// URL that has to answer with `204 No Content`
+    Http(String),
+}
+
+impl Probe {
+    /// URLs are checked over HTTP, anything else is a `host:port` to connect to
+    pub fn new(target: &str) -> Self {
+        if target.starts_with("http://") || target.starts_with("https://") {
+            Self::Http(target.into())
+        } else {
+            Self::Tcp(target.into())
+        }
+    }
+
+    fn check(&self, agent: &ureq::Agent) -> bool {
+        match self {
+            Self::Tcp(addr) => addr.to_socket_addrs().is_ok_and(|mut addrs| {
+                addrs.any(|a| TcpStream::connect_timeout(&a, PROBE_TIMEOUT).is_ok())
+            }),
+            Self::Http(url) => agent.get(url).call().is_ok_and(|r| r.status() == 204),
+        }
+    }
+}
+
+pub struct Internet {
+    ignore: Vec<glob::Pattern>,
+    format: String,
+
+    route: Option<netlink::Socket>,
+    nl80211: Option<(netlink::Socket, u16)>,
+
+    link: RefCell<Option<Link>>,
+    watching: Arc<AtomicBool>,
+    dirty: Arc<AtomicBool>,
+    redraw: mpsc::Sender<()>,
+
+    probe: Option<mpsc::Sender<()>>,
+    online: Arc<RwLock<Option<bool>>>,
+}
 
 impl Internet {
-    pub const fn new() -> Self {
-        Self {}
+    pub fn new(redraw: mpsc::Sender<()>) -> Self {
+        let nl80211 = netlink::Socket::new(libc::NETLINK_GENERIC, 0)
+            .and_then(|s| s.genl_family("nl80211").map(|f| (s, f)));
+        if let Err(e) = &nl80211 {
+            eprintln!("nl80211 not available: {e}");
+        }
+
+        // the cached link is dirty whenever an interface, address or route changes
+        let watching = Arc::new(AtomicBool::new(false));
+        let dirty = Arc::new(AtomicBool::new(true));
+        let d = dirty.clone();
+        netlink::listen(
+            libc::NETLINK_ROUTE,
+            RTNL_GROUPS,
+            "rtnetlink events",
+            &watching,
+            redraw.clone(),
+            move |_| {
+                d.store(true, Ordering::Relaxed);
+                true
+            },
+        );
+
+        Self {
+            ignore: vec![],
+            format: DEFAULT_FORMAT.into(),
+
+            route: netlink::Socket::new(libc::NETLINK_ROUTE, 0).ok(),
+            nl80211: nl80211.ok(),
+
+            link: RefCell::new(None),
+            watching,
+            dirty,
+            redraw,
+
+            probe: None,
+            online: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Periodically checks connectivity in the background and whenever the link changes
+    pub fn probe(mut self, probe: Probe, interval: Duration) -> Self {
+        let (tx, rx) = mpsc::channel::<()>();
+        let online = self.online.clone();
+        let redraw = self.redraw.clone();
+        std::thread::spawn(move || {
+            let agent: ureq::Agent = ureq::Agent::config_builder()
+                .timeout_global(Some(PROBE_TIMEOUT))
+                .max_redirects(0)
+                .http_status_as_error(false)
+                .tls_config(
+                    ureq::tls::TlsConfig::builder()
+                        .provider(ureq::tls::TlsProvider::Rustls)
+                        .build(),
+                )
+                .build()
+                .into();
+
+            loop {
+                let new = Some(probe.check(&agent));
+                if let Ok(mut w) = online.write()
+                    && *w != new
+                {
+                    *w = new;
+                    let _ = redraw.send(());
+                }
+                if let Err(mpsc::RecvTimeoutError::Disconnected) = rx.recv_timeout(interval) {
+                    break;
+                }
+            }
+        });
+
+        self.probe = Some(tx);
+        self
+    }
+    /// Placeholders: `{icon}`, `{name}`, `{ssid}`, `{quality}`, `{signal}` (dBm), `{bitrate}`
+    /// (Mbit/s), `{ipv4}` and `{ipv6}`
+    pub fn format(mut self, format: &str) -> Self {
+        self.format = format.into();
+        self
+    }
+
+    /// Interfaces matching one of these glob patterns are never shown, e.g. `docker*`
+    pub fn ignore(mut self, patterns: &[&str]) -> Result<Self, anyhow::Error> {
+        for p in patterns {
+            self.ignore.push(glob::Pattern::new(p)?);
+        }
+        Ok(self)
+    }
+
+    fn interfaces(&self) -> Result<Vec<Interface>, anyhow::Error> {
+        let mut out = vec![];
+        for dev in std::fs::read_dir("/sys/class/net")?.flatten() {
+            let Ok(name) = dev.file_name().into_string() else {
+                continue;
+            };
+            if self.ignore.iter().any(|p| p.matches(&name)) {
+                continue;
+            }
+            let path = dev.path();
+            let Some(kind) = classify(&path) else {
+                continue;
+            };
+            let index = std::fs::read_to_string(path.join("ifindex"))?
+                .trim()
+                .parse::<u32>()?;
+            let up = is_up(&path)?;
+            out.push(Interface {
+                name,
+                index,
+                kind,
+                up,
+            });
+        }
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(out)
+    }
+
+    /// Returns `None` if there are no interfaces at all
+    fn link(&self) -> Result<Option<Link>, anyhow::Error> {
+        let interfaces = self.interfaces()?;
+        if interfaces.is_empty() {
+            return Ok(None);
+        }
+
+        let route = default_route();
+        let iface = interfaces
+            .into_iter()
+            .find(|i| i.up && route.as_ref() == Some(&i.name));
+        let (ipv4, ipv6) = match (&self.route, &iface) {
+            (Some(sock), Some(iface)) => addresses(sock, iface.index),
+            _ => (None, None),
+        };
+        Ok(Some(Link { iface, ipv4, ipv6 }))
     }
 }
 
 impl super::Block for Internet {
     fn run(&self) -> Result<Option<String>, anyhow::Error> {
-        let tuple = std::fs::read_to_string("/proc/net/wireless")?
-            .lines()
-            .find(|s| s.starts_with('w'))
-            .map(|v| {
-                let a = v.split_whitespace().collect::<Vec<_>>();
-                (
-                    a.first().map(|v| {
-                        let mut v = (*v).to_string();
-                        v.pop();
-                        v
-                    }),
-                    a.get(2).and_then(|v| v.parse::<f32>().ok()),
-                )
-            });
-        let Some((Some(id), Some(val))) = tuple else {
+        if !self.watching.load(Ordering::Relaxed) || self.dirty.swap(false, Ordering::Relaxed) {
+            let link = self.link();
+            if link.is_err() {
+                // try again next tick
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+            let link = link?;
+            if *self.link.borrow() != link
+                && let Some(probe) = &self.probe
+            {
+                let _ = probe.send(());
+            }
+            *self.link.borrow_mut() = link;
+        }
+
+        let Some(link) = self.link.borrow().clone() else {
             return Ok(None);
         };
-        let icon = if std::fs::read_to_string(format!("/sys/class/net/{id}/operstate"))?
-            .lines()
-            .next()
-            == Some("up")
-        {
-            "🌍"
+        let Some(iface) = link.iface else {
+            return Ok(Some("❎".into()));
+        };
+
+        let icon = if *self.online.read().unwrap() == Some(false) {
+            "🚫"
         } else {
-            "❎"
+            iface.kind.icon()
         };
 
-        #[allow(clippy::cast_possible_truncation)]
-        let val = (val * 100.0 / 70.0) as i32;
-        Ok(Some(format!("{icon} {val}%")))
+        let wifi = match &self.nl80211 {
+            Some((sock, family)) if iface.kind == Kind::Wireless => {
+                wifi_info(sock, *family, iface.index)
+            }
+            _ => WifiInfo::default(),
+        };
+        let quality = if iface.kind == Kind::Wireless {
+            quality(&iface.name, wifi.signal)
+        } else {
+            None
+        };
+
+        Ok(Some(super::format(
+            &self.format,
+            &[
+                ("icon", Some(icon.into())),
+                ("name", Some(iface.name)),
+                ("ssid", wifi.ssid),
+                ("quality", quality.map(|v| v.to_string())),
+                ("signal", wifi.signal.map(|v| v.to_string())),
+                (
+                    "bitrate",
+                    wifi.bitrate.map(|v| format!("{:.1}", f64::from(v) / 10.0)),
+                ),
+                ("ipv4", link.ipv4.map(|v| v.to_string())),
+                ("ipv6", link.ipv6.map(|v| v.to_string())),
+            ],
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn probe_target() {
+        assert!(matches!(
+            Probe::new("http://connectivitycheck.gstatic.com/generate_204"),
+            Probe::Http(_)
+        ));
+        assert!(matches!(Probe::new("1.1.1.1:443"), Probe::Tcp(_)));
+    }
+
+    #[test]
+    fn tcp_probe() {
+        let agent = ureq::Agent::new_with_defaults();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let probe = Probe::new(&listener.local_addr().unwrap().to_string());
+        assert!(probe.check(&agent));
+
+        drop(listener);
+        assert!(!probe.check(&agent));
+    }
+
+    #[test]
+    fn classify_tun_and_tap() {
+        let dev = std::env::temp_dir().join(format!("ministatus-tun-{}", std::process::id()));
+        std::fs::create_dir_all(&dev).unwrap();
+        std::fs::write(dev.join("type"), "1\n").unwrap();
+
+        // IFF_TUN | IFF_NO_PI, e.g. OpenVPN
+        std::fs::write(dev.join("tun_flags"), "0x1001\n").unwrap();
+        assert_eq!(classify(&dev), Some(Kind::Tunnel));
+        // IFF_TAP | IFF_NO_PI, e.g. qemu or libvirt `vnet*`
+        std::fs::write(dev.join("tun_flags"), "0x1002\n").unwrap();
+        assert_eq!(classify(&dev), Some(Kind::Ethernet));
+
+        std::fs::remove_dir_all(&dev).unwrap();
     }
 }
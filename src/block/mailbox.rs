@@ -1,30 +1,792 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::SystemTime;
+
+use super::notmuch::Notmuch;
+use crate::inotify::Inotify;
+
+// something appeared in or left a directory
+const CHANGES: u32 =
+    libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_ONLYDIR;
+
+/// How counts are summed up in the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    /// `📬 4`
+    Total,
+    /// `📬 work:3 personal:1`
+    Account,
+    /// `📬 work/INBOX:2 work/lists:1 personal/INBOX:1`, mboxes only by their name
+    Folder,
+}
+
+impl std::str::FromStr for Group {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "total" => Ok(Self::Total),
+            "account" => Ok(Self::Account),
+            "folder" => Ok(Self::Folder),
+            _ => Err(anyhow::anyhow!("usage: mailbox group total|account|folder")),
+        }
+    }
+}
+
 pub struct Mailbox {
-    pattern: String,
+    home: PathBuf,
+    // every directory in a root is an account
+    roots: Vec<PathBuf>,
+    // globs relative to an account, matching maildirs
+    folders: Vec<String>,
+    exclude: Vec<glob::Pattern>,
+    group: Cell<Group>,
+    unread: bool,
+    flagged: bool,
+    watch: Option<Watch>,
+    notmuch: Option<Notmuch>,
+    // `(account, folder, path, counts)` of every maildir as of the last scan
+    cache: RefCell<Vec<(String, String, PathBuf, Counts)>>,
+    mboxes: Vec<PathBuf>,
+    // size and modification time of every mbox when it was counted
+    spools: RefCell<HashMap<PathBuf, (u64, SystemTime, Counts)>>,
 }
 
 impl Mailbox {
-    pub fn new(home: &str) -> Result<Self, anyhow::Error> {
-        if !std::path::Path::new(&format!("{home}/.local/share/mail/")).exists() {
+    /// Looks for `~/.local/share/mail/<account>/INBOX` unless configured otherwise
+    pub fn new(home: &str, redraw: mpsc::Sender<()>) -> Self {
+        let watch = match Watch::new(redraw) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("mailbox updates not available, polling instead: {e}");
+                None
+            }
+        };
+        let home = PathBuf::from(home);
+        Self {
+            roots: vec![home.join(".local/share/mail")],
+            home,
+            folders: vec!["INBOX".into()],
+            exclude: vec![],
+            group: Cell::new(Group::Total),
+            unread: false,
+            flagged: false,
+            watch,
+            notmuch: None,
+            cache: RefCell::new(vec![]),
+            mboxes: vec![],
+            spools: RefCell::default(),
+        }
+    }
+
+    /// Directories containing one directory per account, relative to `$HOME` unless absolute
+    pub fn roots(mut self, roots: &[&str]) -> Self {
+        self.roots = roots.iter().map(|r| self.home.join(r)).collect();
+        self
+    }
+
+    /// mbox files to count as well, e.g. the spool `/var/mail/<user>`. They are shown as an
+    /// account named after the file.
+    pub fn mbox(mut self, mboxes: &[&str]) -> Self {
+        self.mboxes = mboxes.iter().map(|m| self.home.join(m)).collect();
+        self
+    }
+
+    /// Globs matching the maildirs of an account relative to it, e.g. `Inbox` or `.*` for
+    /// Maildir++ subfolders
+    pub fn folders(mut self, folders: &[&str]) -> Self {
+        self.folders = folders.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    /// Globs of folders to leave out relative to their account, e.g. `Spam` or `*/Trash`
+    pub fn exclude(mut self, exclude: &[&str]) -> Result<Self, anyhow::Error> {
+        self.exclude = exclude
+            .iter()
+            .map(|v| glob::Pattern::new(v))
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    /// Can be changed later with `mailbox group total|account|folder`
+    pub fn group(self, group: Group) -> Self {
+        self.group.set(group);
+        self
+    }
+
+    /// Also counts messages in `cur/` that have not been read yet, not just newly delivered ones
+    pub const fn unread(mut self, unread: bool) -> Self {
+        self.unread = unread;
+        self
+    }
+
+    /// Shows how many messages are flagged, e.g. `🚩 2`
+    pub const fn flagged(mut self, flagged: bool) -> Self {
+        self.flagged = flagged;
+        self
+    }
+
+    /// Fails if there is nothing to count, neither a root nor an mbox exists and notmuch is not
+    /// set up
+    pub fn build(self) -> Result<Self, anyhow::Error> {
+        if self.notmuch.is_none() && !self.roots.iter().chain(&self.mboxes).any(|r| r.exists()) {
             return Err(anyhow::anyhow!("mailbox does not exist"));
         }
+        Ok(self)
+    }
 
-        Ok(Self {
-            pattern: format!("{home}/.local/share/mail/*/INBOX/new/*"),
-        })
+    /// The account directories of every root, sorted per root
+    fn accounts(&self) -> Vec<PathBuf> {
+        let mut out = vec![];
+        for root in &self.roots {
+            let Ok(entries) = std::fs::read_dir(root) else {
+                continue;
+            };
+            let mut accounts = entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect::<Vec<_>>();
+            accounts.sort();
+            out.extend(accounts);
+        }
+        out
+    }
+
+    /// Shows the counts of notmuch queries as `(label, query)` instead of the maildirs, e.g.
+    /// `("inbox", "tag:inbox and tag:unread")`. Maildirs are still counted if notmuch is not set
+    /// up, mboxes always since notmuch does not index them.
+    pub fn notmuch(mut self, queries: &[(&str, &str)]) -> Self {
+        match Notmuch::new(&self.home, queries) {
+            Ok(v) => self.notmuch = Some(v),
+            Err(e) => eprintln!("notmuch not available, counting maildirs instead: {e}"),
+        }
+        self
+    }
+
+    /// All maildirs of `accounts` as `(account, folder, path)`, every one only once
+    fn maildirs(
+        &self,
+        accounts: &[PathBuf],
+    ) -> Result<Vec<(String, String, PathBuf)>, anyhow::Error> {
+        let mut seen = HashSet::new();
+        let mut out = vec![];
+        for account in accounts {
+            let name = file_name(account);
+            // only the configured folders are patterns, accounts are named whatever
+            let escaped = PathBuf::from(glob::Pattern::escape(&account.to_string_lossy()));
+            for folder in &self.folders {
+                let pattern = escaped.join(folder);
+                for path in glob::glob(&pattern.to_string_lossy())?.filter_map(Result::ok) {
+                    let rel = path.strip_prefix(account).unwrap_or(&path).to_owned();
+                    if !path.join("new").is_dir()
+                        || self.exclude.iter().any(|p| p.matches_path(&rel))
+                        || !seen.insert(path.clone())
+                    {
+                        continue;
+                    }
+                    out.push((name.clone(), rel.to_string_lossy().into_owned(), path));
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Brings the cached counts up to date. With inotify only the maildirs that changed are
+    /// counted again, everything is only looked at when folders come or go or events were lost.
+    fn refresh(&self) -> Result<(), anyhow::Error> {
+        let watch = self
+            .watch
+            .as_ref()
+            .filter(|w| w.alive.load(Ordering::Relaxed));
+        let Some(watch) = watch else {
+            let maildirs = self.maildirs(&self.accounts())?;
+            *self.cache.borrow_mut() = self.count_all(maildirs);
+            return Ok(());
+        };
+
+        if watch.rescan.swap(false, Ordering::Relaxed) {
+            // anything changing from here on is counted again on the next run
+            watch.dirty.lock().unwrap().clear();
+            watch.unwatched.lock().unwrap().clear();
+            let accounts = self.accounts();
+            let maildirs = match self.maildirs(&accounts) {
+                Ok(v) => v,
+                Err(e) => {
+                    watch.rescan.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+            };
+            // folders are created in a root or an account, messages land in `new/` or `cur/`
+            for dir in self.roots.iter().chain(&accounts) {
+                watch.add(dir, None);
+            }
+            for (_, _, path) in &maildirs {
+                watch.add(&path.join("new"), Some(path));
+                if self.unread || self.flagged {
+                    watch.add(&path.join("cur"), Some(path));
+                }
+            }
+            *self.cache.borrow_mut() = self.count_all(maildirs);
+            return Ok(());
+        }
+
+        let dirty = std::mem::take(&mut *watch.dirty.lock().unwrap());
+        let unwatched = watch.unwatched.lock().unwrap();
+        for (_, _, path, counts) in self.cache.borrow_mut().iter_mut() {
+            if dirty.contains(path) || unwatched.contains(path) {
+                *counts = count(path, self.unread, self.flagged);
+            }
+        }
+        Ok(())
+    }
+
+    /// `(account, folder, path, counts)` of every mbox, only read again when it changed
+    fn spools(&self) -> Vec<(String, String, PathBuf, Counts)> {
+        let mut spools = self.spools.borrow_mut();
+        let mut out = vec![];
+        for path in &self.mboxes {
+            // spools are removed or truncated once everything is read
+            let Some(meta) = std::fs::metadata(path)
+                .ok()
+                .filter(std::fs::Metadata::is_file)
+            else {
+                spools.remove(path);
+                continue;
+            };
+            let stamp = (
+                meta.len(),
+                meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            );
+            let counts = match spools.get(path) {
+                Some((len, modified, c)) if (*len, *modified) == stamp => *c,
+                _ => match count_mbox(path, self.unread, self.flagged) {
+                    Ok(c) => {
+                        spools.insert(path.clone(), (stamp.0, stamp.1, c));
+                        c
+                    }
+                    Err(e) => {
+                        eprintln!("failed to read {}: {e}", path.display());
+                        continue;
+                    }
+                },
+            };
+            out.push((file_name(path), String::new(), path.clone(), counts));
+        }
+        out
+    }
+
+    fn count_all(
+        &self,
+        maildirs: Vec<(String, String, PathBuf)>,
+    ) -> Vec<(String, String, PathBuf, Counts)> {
+        maildirs
+            .into_iter()
+            .map(|(account, folder, path)| {
+                let c = count(&path, self.unread, self.flagged);
+                (account, folder, path, c)
+            })
+            .collect()
+    }
+
+    /// The labelled counts of the notmuch queries and the mbox spools, which notmuch does not
+    /// index, and how many spool messages are flagged
+    fn notmuch_counts(&self, notmuch: &Notmuch) -> Result<(Vec<String>, usize), anyhow::Error> {
+        let mut parts = notmuch
+            .counts()?
+            .into_iter()
+            .filter(|(_, c)| *c > 0)
+            .map(|(label, c)| {
+                if label.is_empty() {
+                    c.to_string()
+                } else {
+                    format!("{label}:{c}")
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut flagged = 0;
+        for (account, _, _, c) in self.spools() {
+            if c.unread > 0 {
+                parts.push(format!("{account}:{}", c.unread));
+            }
+            flagged += c.flagged;
+        }
+        Ok((parts, flagged))
+    }
+
+    /// The counts of maildirs and mbox spools summed up by `group`, and how many messages are
+    /// flagged in total
+    fn maildir_counts(&self) -> Result<(Vec<String>, usize), anyhow::Error> {
+        self.refresh()?;
+
+        let group = self.group.get();
+        let mut counts: Vec<(String, Counts)> = vec![];
+        let mut total = Counts::default();
+        for (account, folder, _, c) in self.cache.borrow().iter().chain(&self.spools()) {
+            let label = match group {
+                Group::Total => String::new(),
+                Group::Folder if !folder.is_empty() => format!("{account}/{folder}"),
+                Group::Account | Group::Folder => account.clone(),
+            };
+            let c = *c;
+            total.add(c);
+            match counts.iter_mut().find(|(l, _)| *l == label) {
+                Some((_, v)) => v.add(c),
+                None => counts.push((label, c)),
+            }
+        }
+
+        let parts = counts
+            .into_iter()
+            .filter(|(_, c)| c.unread > 0)
+            .map(|(label, c)| {
+                if group == Group::Total {
+                    c.unread.to_string()
+                } else {
+                    format!("{label}:{}", c.unread)
+                }
+            })
+            .collect();
+        Ok((parts, total.flagged))
+    }
+}
+
+/// Inotify watches on the mail directories, telling which maildirs need to be counted again
+struct Watch {
+    inotify: Arc<Inotify>,
+    // the maildir a watch descriptor is for, `None` for roots and accounts
+    wds: Arc<Mutex<HashMap<i32, Option<PathBuf>>>>,
+    dirty: Arc<Mutex<HashSet<PathBuf>>>,
+    // maildirs we failed to watch, e.g. beyond `max_user_watches`, counted on every run
+    unwatched: Mutex<HashSet<PathBuf>>,
+    rescan: Arc<AtomicBool>,
+    // false once reading events or watching a folder failed, the counts are polled from then on
+    alive: Arc<AtomicBool>,
+}
+
+impl Watch {
+    fn new(redraw: mpsc::Sender<()>) -> Result<Self, anyhow::Error> {
+        let watch = Self {
+            inotify: Arc::new(Inotify::new()?),
+            wds: Arc::default(),
+            dirty: Arc::default(),
+            unwatched: Mutex::default(),
+            rescan: Arc::new(AtomicBool::new(true)),
+            alive: Arc::new(AtomicBool::new(true)),
+        };
+
+        let inotify = watch.inotify.clone();
+        let wds = watch.wds.clone();
+        let dirty = watch.dirty.clone();
+        let rescan = watch.rescan.clone();
+        let alive = watch.alive.clone();
+        std::thread::spawn(move || {
+            loop {
+                let events = match inotify.read() {
+                    Ok(v) => v,
+                    Err(e) => {
+                        eprintln!("mailbox updates stopped, polling instead: {e}");
+                        break;
+                    }
+                };
+                let mut changed = false;
+                let mut wds = wds.lock().unwrap();
+                for event in events {
+                    if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                        rescan.store(true, Ordering::Relaxed);
+                        changed = true;
+                        continue;
+                    }
+                    // the directory is gone, or was watched for a maildir that no longer is
+                    if event.mask & libc::IN_IGNORED != 0 {
+                        if wds.remove(&event.wd).is_some() {
+                            rescan.store(true, Ordering::Relaxed);
+                            changed = true;
+                        }
+                        continue;
+                    }
+                    match wds.get(&event.wd) {
+                        Some(Some(maildir)) => {
+                            dirty.lock().unwrap().insert(maildir.clone());
+                            changed = true;
+                        }
+                        Some(None) if event.mask & libc::IN_ISDIR != 0 => {
+                            rescan.store(true, Ordering::Relaxed);
+                            changed = true;
+                        }
+                        // added before we knew about it, it is counted anyway
+                        _ => (),
+                    }
+                }
+                drop(wds);
+                if changed && redraw.send(()).is_err() {
+                    break;
+                }
+            }
+            alive.store(false, Ordering::Relaxed);
+        });
+        Ok(watch)
+    }
+
+    /// Watches `dir` for changes, of messages if it belongs to `maildir` and of folders otherwise
+    fn add(&self, dir: &Path, maildir: Option<&PathBuf>) {
+        let e = match self.inotify.add_watch(dir, CHANGES) {
+            Ok(wd) => {
+                self.wds.lock().unwrap().insert(wd, maildir.cloned());
+                return;
+            }
+            Err(e) => e,
+        };
+        let gone = e
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound);
+        match maildir {
+            Some(maildir) => {
+                if !gone {
+                    eprintln!(
+                        "failed to watch {}, counting it every time: {e}",
+                        dir.display()
+                    );
+                }
+                self.unwatched.lock().unwrap().insert(maildir.clone());
+            }
+            // which we hear about on the parent
+            None if gone => (),
+            // new folders would go unnoticed
+            None => {
+                eprintln!("failed to watch {}, polling instead: {e}", dir.display());
+                self.alive.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Flags of a message from the `:2,` suffix of its file name, e.g. `FRS`
+fn flags(name: &str) -> &str {
+    name.rsplit_once(":2,").map_or("", |(_, v)| v)
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    unread: usize,
+    flagged: usize,
+}
+
+impl Counts {
+    fn add(&mut self, other: Self) {
+        self.unread += other.unread;
+        self.flagged += other.flagged;
     }
 }
 
+/// Counts the messages in an mbox by their `Status:` headers. Messages without one are new,
+/// `O` means old but still unread and `R` read, so without `all_unread` only new ones count.
+/// Flags come from `X-Status:`, `F` for flagged and `D` for deleted.
+fn count_mbox(path: &Path, all_unread: bool, flagged: bool) -> Result<Counts, anyhow::Error> {
+    let mut counts = Counts::default();
+    // status and x-status of the message whose headers are being read
+    let mut message: Option<(String, String)> = None;
+    let mut finish = |status: &str, x_status: &str| {
+        if x_status.contains('D') {
+            return;
+        }
+        if !status.contains('R') && (all_unread || !status.contains('O')) {
+            counts.unread += 1;
+        }
+        if flagged && x_status.contains('F') {
+            counts.flagged += 1;
+        }
+    };
+
+    let mut prev_blank = true;
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut line = vec![];
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+
+        if prev_blank && text.starts_with("From ") {
+            if let Some((status, x_status)) = message.take() {
+                finish(&status, &x_status);
+            }
+            message = Some((String::new(), String::new()));
+        } else if let Some((status, x_status)) = message.as_mut() {
+            // headers end at the first blank line, the body is not looked at
+            if text.is_empty() {
+                finish(status, x_status);
+                message = None;
+            } else if let Some((key, value)) = text.split_once(':') {
+                if key.eq_ignore_ascii_case("status") {
+                    *status = value.trim().to_string();
+                } else if key.eq_ignore_ascii_case("x-status") {
+                    *x_status = value.trim().to_string();
+                }
+            }
+        }
+        prev_blank = text.is_empty();
+    }
+    // a message without a body
+    if let Some((status, x_status)) = message {
+        finish(&status, &x_status);
+    }
+    Ok(counts)
+}
+
+/// Counts the messages in `maildir`. Everything in `new/` is unread, in `cur/` it is whatever
+/// lacks the seen flag, unless `all_unread` is off. Trashed messages are left out.
+fn count(maildir: &Path, all_unread: bool, flagged: bool) -> Counts {
+    let mut counts = Counts::default();
+    for (dir, is_new) in [("new", true), ("cur", false)] {
+        if !is_new && !all_unread && !flagged {
+            continue;
+        }
+        let Ok(entries) = std::fs::read_dir(maildir.join(dir)) else {
+            continue;
+        };
+        for e in entries.filter_map(Result::ok) {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            let flags = flags(&name);
+            if flags.contains('T') {
+                continue;
+            }
+            if is_new || (all_unread && !flags.contains('S')) {
+                counts.unread += 1;
+            }
+            if flagged && flags.contains('F') {
+                counts.flagged += 1;
+            }
+        }
+    }
+    counts
+}
+
 impl super::Block for Mailbox {
+    fn command(&self, args: &[&str]) -> Option<Result<(), anyhow::Error>> {
+        let ["mailbox", args @ ..] = args else {
+            return None;
+        };
+        Some(match args {
+            ["group", group] => group.parse().map(|g| self.group.set(g)),
+            _ => Err(anyhow::anyhow!("usage: mailbox group total|account|folder")),
+        })
+    }
+
     fn run(&self) -> Result<Option<String>, anyhow::Error> {
-        let mut c = 0;
-        for _ in glob::glob(&self.pattern)? {
-            c += 1;
-        }
+        let (parts, flagged) = match &self.notmuch {
+            Some(notmuch) => self.notmuch_counts(notmuch)?,
+            None => self.maildir_counts()?,
+        };
 
-        if c == 0 {
+        let mut out = vec![];
+        if !parts.is_empty() {
+            out.push(format!("📬 {}", parts.join(" ")));
+        }
+        if self.flagged && flagged > 0 {
+            out.push(format!("🚩 {flagged}"));
+        }
+        if out.is_empty() {
             Ok(None)
         } else {
-            Ok(Some(format!("📬 {c}")))
+            Ok(Some(out.join(" ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+
+    /// A fresh directory to use as `$HOME`, removed when dropped
+    struct Home(PathBuf);
+
+    impl Home {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("ministatus-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
         }
+
+        /// Creates a maildir at `rel` with the given messages in `new/` and `cur/`
+        fn maildir(&self, rel: &str, new: &[&str], cur: &[&str]) {
+            let dir = self.0.join(rel);
+            for sub in ["new", "cur", "tmp"] {
+                std::fs::create_dir_all(dir.join(sub)).unwrap();
+            }
+            for (sub, names) in [("new", new), ("cur", cur)] {
+                for name in names {
+                    std::fs::write(dir.join(sub).join(name), "").unwrap();
+                }
+            }
+        }
+
+        fn mailbox(&self) -> Mailbox {
+            let (tx, _) = mpsc::channel();
+            Mailbox::new(self.0.to_str().unwrap(), tx)
+        }
+    }
+
+    impl Drop for Home {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn group() {
+        let home = Home::new("group");
+        home.maildir("Mail/work/Inbox", &["1", "2"], &[]);
+        home.maildir("Mail/work/.lists", &["3"], &[]);
+        home.maildir("Mail/work/.Spam", &["4"], &[]);
+        home.maildir("Mail/personal/Inbox", &["5"], &[]);
+        let mailbox = home
+            .mailbox()
+            .roots(&["Mail"])
+            .folders(&["Inbox", ".*"])
+            .exclude(&[".Spam"])
+            .and_then(Mailbox::build)
+            .unwrap();
+
+        assert_eq!(mailbox.run().unwrap().as_deref(), Some("📬 4"));
+        mailbox
+            .command(&["mailbox", "group", "account"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            mailbox.run().unwrap().as_deref(),
+            Some("📬 personal:1 work:3")
+        );
+        mailbox
+            .command(&["mailbox", "group", "folder"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            mailbox.run().unwrap().as_deref(),
+            Some("📬 personal/Inbox:1 work/Inbox:2 work/.lists:1")
+        );
+        assert!(
+            mailbox
+                .command(&["mailbox", "group", "tag"])
+                .unwrap()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn flags_of_name() {
+        assert_eq!(flags("1700000000.M1P2.host:2,FS"), "FS");
+        assert_eq!(flags("1700000000.M1P2.host:2,"), "");
+        assert_eq!(flags("1700000000.M1P2.host"), "");
+    }
+
+    #[test]
+    fn count_maildir() {
+        let home = Home::new("count");
+        home.maildir(
+            "INBOX",
+            &["1", "2:2,F"],
+            &["3:2,", "4:2,S", "5:2,FS", "6:2,T", "7:2,FT", ".8:2,"],
+        );
+        let maildir = home.0.join("INBOX");
+
+        let c = count(&maildir, false, false);
+        assert_eq!((c.unread, c.flagged), (2, 0));
+        let c = count(&maildir, true, false);
+        assert_eq!((c.unread, c.flagged), (3, 0));
+        let c = count(&maildir, false, true);
+        assert_eq!((c.unread, c.flagged), (2, 2));
+        let c = count(&maildir, true, true);
+        assert_eq!((c.unread, c.flagged), (3, 2));
+    }
+
+    #[test]
+    fn count_spool() {
+        let home = Home::new("spool");
+        let spool = home.0.join("spool");
+        std::fs::write(
+            &spool,
+            "From a@example.org Mon Oct 19 08:00:00 2026\n\
+             Subject: new\n\
+             \n\
+             >From the body, escaped\n\
+             \n\
+             From b@example.org Mon Oct 19 08:00:00 2026\n\
+             Status: O\n\
+             X-Status: F\n\
+             \n\
+             body\n\
+             \n\
+             From c@example.org Mon Oct 19 08:00:00 2026\n\
+             Status: RO\n\
+             X-Status: F\n\
+             \n\
+             From d@example.org Mon Oct 19 08:00:00 2026\n\
+             X-Status: DF\n\
+             \n\
+             From e@example.org Mon Oct 19 08:00:00 2026\n\
+             status: \n",
+        )
+        .unwrap();
+
+        let c = count_mbox(&spool, false, false).unwrap();
+        assert_eq!((c.unread, c.flagged), (2, 0));
+        let c = count_mbox(&spool, true, true).unwrap();
+        assert_eq!((c.unread, c.flagged), (3, 2));
+    }
+
+    #[test]
+    fn build_in_any_order() {
+        let home = Home::new("build");
+        assert!(home.mailbox().roots(&["Mail"]).build().is_err());
+
+        std::fs::write(home.0.join("spool"), "").unwrap();
+        assert!(
+            home.mailbox()
+                .roots(&["Mail"])
+                .mbox(&["spool"])
+                .build()
+                .is_ok()
+        );
+        assert!(
+            home.mailbox()
+                .mbox(&["spool"])
+                .roots(&["Mail"])
+                .build()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn accounts_are_not_patterns() {
+        let home = Home::new("escape");
+        home.maildir("Mail/[w]ork*/INBOX", &["1"], &[]);
+        home.maildir("Mail/w/INBOX", &["2", "3"], &[]);
+        let mailbox = home
+            .mailbox()
+            .roots(&["Mail"])
+            .group(Group::Account)
+            .build()
+            .unwrap();
+        assert_eq!(mailbox.run().unwrap().as_deref(), Some("📬 [w]ork*:1 w:2"));
     }
 }
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, mpsc};
+
+use zbus::blocking::{Connection, MessageIterator, fdo::DBusProxy};
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{MatchRule, message::Type};
+
+const PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+// ordered by how much we want to show a player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Debug, Clone)]
+struct Track {
+    // bus name of the player, commands go there
+    player: String,
+    status: Status,
+    artist: Option<String>,
+    title: Option<String>,
+}
+
+/// Values in `a{sv}` dictionaries may come wrapped in variants
+fn inner<'a, 'b>(v: &'b Value<'a>) -> &'b Value<'a> {
+    match v {
+        Value::Value(v) => inner(v),
+        v => v,
+    }
+}
+
+fn string(v: &Value<'_>) -> Option<String> {
+    <&str>::try_from(inner(v)).ok().map(ToString::to_string)
+}
+
+/// Playback status and metadata of the player owning `name`
+fn track(conn: &Connection, name: &str) -> Result<Track, anyhow::Error> {
+    let reply = conn.call_method(
+        Some(name),
+        PATH,
+        Some("org.freedesktop.DBus.Properties"),
+        "GetAll",
+        &(PLAYER,),
+    )?;
+    let props: HashMap<String, OwnedValue> = reply.body().deserialize()?;
+    Ok(parse(name, &props))
+}
+
+/// The track from the properties of a player's `org.mpris.MediaPlayer2.Player` interface
+fn parse(name: &str, props: &HashMap<String, OwnedValue>) -> Track {
+    let status = match props
+        .get("PlaybackStatus")
+        .and_then(|v| string(v))
+        .as_deref()
+    {
+        Some("Playing") => Status::Playing,
+        Some("Paused") => Status::Paused,
+        _ => Status::Stopped,
+    };
+
+    let mut artist = None;
+    let mut title = None;
+    if let Some(Value::Dict(metadata)) = props.get("Metadata").map(|v| inner(v)) {
+        for (k, v) in metadata.iter() {
+            match <&str>::try_from(inner(k)) {
+                Ok("xesam:title") => title = string(v),
+                Ok("xesam:artist") => {
+                    if let Value::Array(a) = inner(v) {
+                        let names = a.inner().iter().filter_map(string).collect::<Vec<_>>();
+                        artist = Some(names.join(", ")).filter(|v| !v.is_empty());
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Track {
+        player: name.to_string(),
+        status,
+        artist,
+        title,
+    }
+}
+
+/// The track of the player to show out of all players on the bus
+fn active(conn: &Connection, prefer: &[String]) -> Result<Option<Track>, anyhow::Error> {
+    let dbus = DBusProxy::new(conn)?;
+    let mut tracks = vec![];
+    for name in dbus.list_names()? {
+        if !name.starts_with(PREFIX) {
+            continue;
+        }
+        // the player might have gone away in the meantime
+        if let Ok(track) = track(conn, &name) {
+            tracks.push(track);
+        }
+    }
+    Ok(pick(tracks, prefer))
+}
+
+/// Playing tracks first, then the player that comes earliest in `prefer`
+fn pick(tracks: Vec<Track>, prefer: &[String]) -> Option<Track> {
+    let rank = |name: &str| {
+        let id = name.strip_prefix(PREFIX).unwrap_or(name);
+        prefer
+            .iter()
+            .position(|p| id.starts_with(p.as_str()))
+            .unwrap_or(usize::MAX)
+    };
+    tracks
+        .into_iter()
+        .filter(|t| t.status != Status::Stopped)
+        .min_by_key(|t| (t.status, rank(&t.player)))
+}
+
+/// Forwards every message matching `rule` as a wakeup until the bus goes away
+fn forward(conn: &Connection, rule: MatchRule<'static>, wake: mpsc::Sender<()>) {
+    let messages = match MessageIterator::for_match_rule(rule, conn, None) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("media updates not available: {e}");
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        for msg in messages {
+            if msg.is_err() || wake.send(()).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Refreshes the active track whenever a player changes a property, appears or goes away
+fn watch(
+    conn: &Connection,
+    prefer: Vec<String>,
+    state: Arc<RwLock<Option<Track>>>,
+    redraw: mpsc::Sender<()>,
+) -> Result<(), anyhow::Error> {
+    let (wake, woken) = mpsc::channel();
+    forward(
+        conn,
+        MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path(PATH)?
+            .build(),
+        wake.clone(),
+    );
+    forward(
+        conn,
+        MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender("org.freedesktop.DBus")?
+            .interface("org.freedesktop.DBus")?
+            .member("NameOwnerChanged")?
+            .arg0ns("org.mpris.MediaPlayer2")?
+            .build(),
+        wake.clone(),
+    );
+
+    let conn = conn.clone();
+    std::thread::spawn(move || {
+        // the first refresh happens right away
+        let _ = wake.send(());
+        while woken.recv().is_ok() {
+            // players tend to change several properties at once
+            while woken.try_recv().is_ok() {}
+            match active(&conn, &prefer) {
+                Ok(track) => {
+                    if let Ok(mut w) = state.write() {
+                        *w = track;
+                    }
+                    let _ = redraw.send(());
+                }
+                Err(e) => eprintln!("failed to query media players: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+pub struct Media {
+    conn: Connection,
+    state: Arc<RwLock<Option<Track>>>,
+    max_width: usize,
+}
+
+impl Media {
+    /// Players are shown in the order of `prefer` when none is playing, matched against the
+    /// part of their bus name after `org.mpris.MediaPlayer2.`, e.g. `spotify` or `firefox`
+    pub fn new(redraw: mpsc::Sender<()>, prefer: &[&str]) -> Result<Self, anyhow::Error> {
+        let conn = Connection::session()?;
+        let state = Arc::new(RwLock::new(None));
+        watch(
+            &conn,
+            prefer.iter().map(ToString::to_string).collect(),
+            state.clone(),
+            redraw,
+        )?;
+        Ok(Self {
+            conn,
+            state,
+            max_width: usize::MAX,
+        })
+    }
+
+    /// Shortens `artist – title` to this many characters
+    pub const fn max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// `toggle|play|pause|next|previous` on the player that is shown
+    fn control(&self, args: &[&str]) -> Result<(), anyhow::Error> {
+        let method = match args {
+            ["toggle"] => "PlayPause",
+            ["play"] => "Play",
+            ["pause"] => "Pause",
+            ["next"] => "Next",
+            ["previous"] => "Previous",
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "usage: media toggle|play|pause|next|previous"
+                ));
+            }
+        };
+        let Some(player) = self
+            .state
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|t| t.player.clone())
+        else {
+            return Err(anyhow::anyhow!("no player"));
+        };
+        self.conn
+            .call_method(Some(player.as_str()), PATH, Some(PLAYER), method, &())?;
+        Ok(())
+    }
+}
+
+impl super::Block for Media {
+    fn command(&self, args: &[&str]) -> Option<Result<(), anyhow::Error>> {
+        let ["media", args @ ..] = args else {
+            return None;
+        };
+        Some(self.control(args))
+    }
+
+    fn run(&self) -> Result<Option<String>, anyhow::Error> {
+        let r = self.state.read().unwrap();
+        let Some(track) = r.as_ref() else {
+            return Ok(None);
+        };
+
+        let text = match (&track.artist, &track.title) {
+            (Some(artist), Some(title)) => format!("{artist} – {title}"),
+            (None, Some(v)) | (Some(v), None) => v.clone(),
+            (None, None) => return Ok(None),
+        };
+        let text = if text.chars().count() > self.max_width {
+            let mut v = text
+                .chars()
+                .take(self.max_width.saturating_sub(1))
+                .collect::<String>();
+            v.push('…');
+            v
+        } else {
+            text
+        };
+
+        let icon = if track.status == Status::Playing {
+            "▶"
+        } else {
+            "⏸"
+        };
+        Ok(Some(format!("{icon} {text}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(status: &str, metadata: Value<'static>) -> HashMap<String, OwnedValue> {
+        HashMap::from([
+            (
+                "PlaybackStatus".to_string(),
+                OwnedValue::try_from(Value::from(status)).unwrap(),
+            ),
+            (
+                "Metadata".to_string(),
+                OwnedValue::try_from(metadata).unwrap(),
+            ),
+        ])
+    }
+
+    fn player(name: &str, status: Status) -> Track {
+        Track {
+            player: format!("{PREFIX}{name}"),
+            status,
+            artist: None,
+            title: None,
+        }
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let metadata = HashMap::from([
+            ("xesam:title", Value::from("Song")),
+            ("xesam:artist", Value::from(vec!["A", "B"])),
+            ("xesam:album", Value::from("Album")),
+        ]);
+        let t = parse(
+            "org.mpris.MediaPlayer2.mpd",
+            &props("Playing", metadata.into()),
+        );
+        assert_eq!(t.status, Status::Playing);
+        assert_eq!(t.artist.as_deref(), Some("A, B"));
+        assert_eq!(t.title.as_deref(), Some("Song"));
+    }
+
+    #[test]
+    fn parse_nested_variants() {
+        // some players wrap values in variants once more than needed
+        let wrap = |v: Value<'static>| Value::Value(Box::new(v));
+        let metadata = HashMap::from([
+            ("xesam:title", wrap(Value::from("Song"))),
+            ("xesam:artist", wrap(Value::from(Vec::<&str>::new()))),
+        ]);
+        let t = parse(
+            "org.mpris.MediaPlayer2.mpd",
+            &props("Paused", wrap(metadata.into())),
+        );
+        assert_eq!(t.status, Status::Paused);
+        assert_eq!(t.artist, None);
+        assert_eq!(t.title.as_deref(), Some("Song"));
+
+        let t = parse("org.mpris.MediaPlayer2.mpd", &HashMap::new());
+        assert_eq!(t.status, Status::Stopped);
+        assert_eq!((t.artist, t.title), (None, None));
+    }
+
+    #[test]
+    fn pick_order() {
+        let prefer = ["spotify".to_string(), "mpd".to_string()];
+        let picked = |tracks| pick(tracks, &prefer).map(|t| t.player);
+
+        // playing beats preferred
+        assert_eq!(
+            picked(vec![
+                player("spotify", Status::Paused),
+                player("firefox.instance_1", Status::Playing),
+            ]),
+            Some(format!("{PREFIX}firefox.instance_1"))
+        );
+        // then the order of `prefer`, unknown players last
+        assert_eq!(
+            picked(vec![
+                player("firefox", Status::Paused),
+                player("mpd", Status::Paused),
+                player("spotify", Status::Paused),
+            ]),
+            Some(format!("{PREFIX}spotify"))
+        );
+        assert_eq!(
+            picked(vec![
+                player("firefox", Status::Paused),
+                player("mpd", Status::Paused),
+            ]),
+            Some(format!("{PREFIX}mpd"))
+        );
+        // stopped players are never shown
+        assert_eq!(picked(vec![player("spotify", Status::Stopped)]), None);
+    }
+}
@@ -1,19 +1,54 @@
+mod alsa;
 mod battery;
 mod clock;
 mod internet;
 mod mailbox;
+mod media;
 mod news;
+mod notmuch;
+mod peripheral;
+#[cfg(feature = "pipewire")]
+mod pipewire;
 mod pulse;
+mod vpn;
 mod weather;
 
+pub use alsa::Alsa;
 pub use battery::Battery;
 pub use clock::Clock;
-pub use internet::Internet;
-pub use mailbox::Mailbox;
+pub use internet::{Internet, Probe};
+pub use mailbox::{Group as MailGroup, Mailbox};
+pub use media::Media;
 pub use news::News;
+pub use peripheral::PeripheralBattery;
 pub use pulse::Pulse;
+pub use vpn::Vpn;
 pub use weather::Weather;
 
 pub trait Block {
     fn run(&self) -> Result<Option<String>, anyhow::Error>;
+
+    /// Handles `ministatus <args...>` if `args[0]` names this block, `None` otherwise
+    fn command(&self, _args: &[&str]) -> Option<Result<(), anyhow::Error>> {
+        None
+    }
+}
+
+/// Replaces `{key}` placeholders in `format`. Words with a placeholder that has no value are
+/// dropped entirely, so `{signal}dBm` disappears if there is no signal.
+fn format(format: &str, values: &[(&str, Option<String>)]) -> String {
+    format
+        .split_whitespace()
+        .filter_map(|word| {
+            let mut word = word.to_string();
+            for (key, value) in values {
+                let key = format!("{{{key}}}");
+                if word.contains(&key) {
+                    word = word.replace(&key, value.as_deref()?);
+                }
+            }
+            Some(word)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
@@ -0,0 +1,148 @@
+use std::cell::{Cell, RefCell};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+// how often to ask notmuch for its revision when we can not watch the database files instead
+const REVISION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Counts of notmuch queries, only asked for again once the database changed
+pub(super) struct Notmuch {
+    // `(label, query)`
+    queries: Vec<(String, String)>,
+    // the Xapian directory of the database, if we found it
+    xapian: Option<PathBuf>,
+    // newest modification time in `xapian` as of the last run, cheap to look at every tick
+    modified: RefCell<Option<SystemTime>>,
+    revision: RefCell<Option<u64>>,
+    // when we last asked for the revision, only used without `xapian`
+    asked: Cell<Option<Instant>>,
+    counts: RefCell<Vec<(String, usize)>>,
+}
+
+impl Notmuch {
+    /// Fails if notmuch is not installed or not set up
+    pub(super) fn new(home: &Path, queries: &[(&str, &str)]) -> Result<Self, anyhow::Error> {
+        let path = notmuch(&["config", "get", "database.path"], None)?;
+        let path = home.join(path.trim());
+        let xapian = xapian(home, &path);
+        if xapian.is_none() {
+            eprintln!(
+                "notmuch database not found, asking for its revision every {}s",
+                REVISION_INTERVAL.as_secs()
+            );
+        }
+        Ok(Self {
+            queries: queries
+                .iter()
+                .map(|(l, q)| ((*l).to_string(), (*q).to_string()))
+                .collect(),
+            xapian,
+            modified: RefCell::new(None),
+            revision: RefCell::new(None),
+            asked: Cell::new(None),
+            counts: RefCell::new(vec![]),
+        })
+    }
+
+    /// `(label, count)` of every query
+    pub(super) fn counts(&self) -> Result<Vec<(String, usize)>, anyhow::Error> {
+        let modified = self.xapian.as_deref().and_then(newest);
+        if modified.is_some() && modified == *self.modified.borrow() {
+            return Ok(self.counts.borrow().clone());
+        }
+        // other blocks wake us up as well, starting a process every time would be too much
+        if self.xapian.is_none() {
+            if self
+                .asked
+                .get()
+                .is_some_and(|t| t.elapsed() < REVISION_INTERVAL)
+            {
+                return Ok(self.counts.borrow().clone());
+            }
+            self.asked.set(Some(Instant::now()));
+        }
+
+        let revision = revision()?;
+        if Some(revision) != *self.revision.borrow() {
+            // one query per line
+            let mut input = self
+                .queries
+                .iter()
+                .map(|(_, q)| q.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            input.push('\n');
+            let out = notmuch(&["count", "--batch"], Some(&input))?;
+            let counts = out
+                .lines()
+                .map(str::parse)
+                .collect::<Result<Vec<usize>, _>>()?;
+            if counts.len() != self.queries.len() {
+                return Err(anyhow::anyhow!("notmuch returned {} counts", counts.len()));
+            }
+            *self.counts.borrow_mut() = self
+                .queries
+                .iter()
+                .map(|(l, _)| l.clone())
+                .zip(counts)
+                .collect();
+            *self.revision.borrow_mut() = Some(revision);
+        }
+        *self.modified.borrow_mut() = modified;
+        Ok(self.counts.borrow().clone())
+    }
+}
+
+/// Runs `notmuch` with `args`, feeding it `input`, and returns what it printed
+fn notmuch(args: &[&str], input: Option<&str>) -> Result<String, anyhow::Error> {
+    let mut child = Command::new("notmuch")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let out = child.wait_with_output()?;
+    if !out.status.success() {
+        return Err(anyhow::anyhow!("notmuch {} failed", args.join(" ")));
+    }
+    Ok(String::from_utf8(out.stdout)?)
+}
+
+/// Revision of the database, it goes up with every change including tags
+fn revision() -> Result<u64, anyhow::Error> {
+    // prints `count uuid revision`, counting everything is cheap for Xapian
+    let out = notmuch(&["count", "--lastmod", "*"], None)?;
+    let revision = out
+        .split_whitespace()
+        .nth(2)
+        .ok_or_else(|| anyhow::anyhow!("unexpected output of notmuch count"))?;
+    Ok(revision.parse()?)
+}
+
+/// Where the Xapian files are, next to the mail or in `$XDG_DATA_HOME` since notmuch 0.32
+fn xapian(home: &Path, path: &Path) -> Option<PathBuf> {
+    let data =
+        std::env::var("XDG_DATA_HOME").map_or_else(|_| home.join(".local/share"), PathBuf::from);
+    let profile = std::env::var("NOTMUCH_PROFILE").unwrap_or_else(|_| "default".into());
+    let mut candidates = vec![];
+    if let Ok(v) = std::env::var("NOTMUCH_DATABASE") {
+        candidates.push(PathBuf::from(v).join("xapian"));
+    }
+    candidates.push(path.join(".notmuch/xapian"));
+    candidates.push(data.join("notmuch").join(profile).join("xapian"));
+    candidates.into_iter().find(|p| p.is_dir())
+}
+
+/// Newest modification time of the files in `dir`
+fn newest(dir: &Path) -> Option<SystemTime> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|e| e.metadata().ok()?.modified().ok())
+        .max()
+}
@@ -0,0 +1,67 @@
+use super::battery::Props;
+
+const DEFAULT_LOW: u8 = 20;
+
+/// Batteries of mice, keyboards, headsets, ... which are listed as power supplies with
+/// `scope=Device`
+pub struct PeripheralBattery {
+    low: u8,
+}
+
+impl PeripheralBattery {
+    pub const fn new() -> Self {
+        Self { low: DEFAULT_LOW }
+    }
+
+    /// Devices below this percentage are marked
+    pub const fn low(mut self, low: u8) -> Self {
+        self.low = low;
+        self
+    }
+
+    fn device(&self, name: &str, props: &Props) -> Option<String> {
+        let model = props.get("model_name").unwrap_or(name);
+        if let Some(cap) = props.capacity() {
+            let sep = if cap < f64::from(self.low) {
+                "❗"
+            } else {
+                " "
+            };
+            return Some(format!("{model}{sep}{cap:.0}%"));
+        }
+        // some devices only report a coarse level
+        match props.get("capacity_level")? {
+            "Unknown" => None,
+            level @ ("Critical" | "Low") => Some(format!("{model}❗{}", level.to_lowercase())),
+            level => Some(format!("{model} {}", level.to_lowercase())),
+        }
+    }
+}
+
+impl super::Block for PeripheralBattery {
+    fn run(&self) -> Result<Option<String>, anyhow::Error> {
+        let mut out = vec![];
+        for ps in std::fs::read_dir("/sys/class/power_supply")?.flatten() {
+            let Ok(name) = ps.file_name().into_string() else {
+                continue;
+            };
+            // devices disappear all the time, so just skip the ones we can't read
+            let Ok(props) = Props::read(&ps.path()) else {
+                continue;
+            };
+            if props.get("scope") != Some("Device") {
+                continue;
+            }
+            if let Some(v) = self.device(&name, &props) {
+                out.push(v);
+            }
+        }
+        out.sort();
+
+        if out.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(format!("🖱️ {}", out.join(", "))))
+        }
+    }
+}
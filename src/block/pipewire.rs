@@ -0,0 +1,637 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, mpsc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use libpulse_binding::volume::{ChannelVolumes, Volume, VolumeLinear};
+use pipewire::{
+    context::ContextRc,
+    core::PW_ID_CORE,
+    device::{Device as PwDevice, DeviceListener},
+    main_loop::MainLoopRc,
+    metadata::{Metadata, MetadataListener},
+    node::{Node, NodeListener},
+    registry::{GlobalObject, Registry},
+    spa::{
+        self,
+        param::ParamType,
+        pod::{
+            Object, Pod, Property, Value, ValueArray, deserialize::PodDeserializer,
+            serialize::PodSerializer,
+        },
+        utils::{Id, dict::DictRef},
+    },
+    types::ObjectType,
+};
+
+use super::pulse::{Action, Audio, Device, Target, TxState, adjust};
+
+// how long the server has to tell us about its objects before we use libpulse instead
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+enum Command {
+    Apply(Target, Action, u32),
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Sink,
+    Source,
+}
+
+/// An `Audio/Sink` or `Audio/Source` node and its last known `Props`
+struct Entry {
+    name: String,
+    class: Class,
+    device: Device,
+    // `device.id` of the card and `card.profile.device`, the index of the route we play through
+    card: Option<(u32, i32)>,
+    volume: Option<ChannelVolumes>,
+    mute: bool,
+    node: Node,
+    _listener: NodeListener,
+}
+
+/// An active route of a card, e.g. the headphones of a built-in one
+struct Route {
+    device: i32,
+    direction: u32,
+    name: String,
+    description: Option<String>,
+}
+
+/// An `Audio/Device`, ports are routes of the card rather than of its nodes
+struct Card {
+    routes: Vec<Route>,
+    _device: PwDevice,
+    _listener: DeviceListener,
+}
+
+#[derive(Default)]
+struct Graph {
+    // `node.name` of the defaults from the `default` metadata
+    default_sink: Option<String>,
+    default_source: Option<String>,
+    nodes: HashMap<u32, Entry>,
+    // `Stream/Input/Audio` nodes, i.e. applications recording
+    streams: HashSet<u32>,
+    metadata: Vec<(Metadata, MetadataListener)>,
+    cards: HashMap<u32, Card>,
+}
+
+impl Graph {
+    fn default_node(&self, class: Class) -> Option<&Entry> {
+        let name = match class {
+            Class::Sink => self.default_sink.as_ref(),
+            Class::Source => self.default_source.as_ref(),
+        }?;
+        self.nodes
+            .values()
+            .find(|e| e.class == class && e.name == *name)
+    }
+
+    /// The device of the default sink with the port it plays through
+    fn sink_device(&self) -> Device {
+        let Some(entry) = self.default_node(Class::Sink) else {
+            return Device::default();
+        };
+        let mut device = entry.device.clone();
+        device.port =
+            entry.card.and_then(|(id, index)| {
+                let route =
+                    self.cards.get(&id)?.routes.iter().find(|r| {
+                        r.device == index && r.direction == spa::sys::SPA_DIRECTION_OUTPUT
+                    })?;
+                Some((route.name.clone(), route.description.clone()))
+            });
+        device
+    }
+
+    fn tx_state(&self, class: Class) -> Option<TxState> {
+        let entry = self.default_node(class)?;
+        Some(TxState::new(entry.volume.as_ref()?, entry.mute))
+    }
+}
+
+/// Everything the callbacks on the `PipeWire` thread share
+struct Monitor {
+    graph: RefCell<Graph>,
+    state: Arc<RwLock<Audio>>,
+    redraw: mpsc::Sender<()>,
+}
+
+impl Monitor {
+    /// Copies the defaults into the state the block renders
+    fn publish(&self) {
+        let graph = self.graph.borrow();
+        let audio = Audio {
+            sink: graph.tx_state(Class::Sink).unwrap_or_default(),
+            device: graph.sink_device(),
+            source: graph.tx_state(Class::Source),
+            recording: !graph.streams.is_empty(),
+        };
+        if let Ok(mut w) = self.state.write() {
+            *w = audio;
+        }
+        let _ = self.redraw.send(());
+    }
+
+    fn global(self: &Rc<Self>, registry: &Registry, obj: &GlobalObject<&DictRef>) {
+        let Some(props) = obj.props else {
+            return;
+        };
+        match obj.type_ {
+            ObjectType::Metadata if props.get("metadata.name") == Some("default") => {
+                self.bind_metadata(registry, obj);
+            }
+            ObjectType::Device if props.get("media.class") == Some("Audio/Device") => {
+                self.bind_card(registry, obj);
+            }
+            ObjectType::Node => {
+                let class = match props.get("media.class") {
+                    Some("Audio/Sink") => Class::Sink,
+                    Some("Audio/Source") => Class::Source,
+                    Some("Stream/Input/Audio") => {
+                        self.graph.borrow_mut().streams.insert(obj.id);
+                        self.publish();
+                        return;
+                    }
+                    _ => return,
+                };
+                self.bind_node(registry, obj, props, class);
+            }
+            _ => (),
+        }
+    }
+
+    fn bind_metadata(self: &Rc<Self>, registry: &Registry, obj: &GlobalObject<&DictRef>) {
+        let Ok(metadata) = registry.bind::<Metadata, _>(obj) else {
+            return;
+        };
+        let weak = Rc::downgrade(self);
+        let listener = metadata
+            .add_listener_local()
+            .property(move |_, key, _, value| {
+                let Some(monitor) = weak.upgrade() else {
+                    return 0;
+                };
+                let name = value.and_then(node_name);
+                {
+                    let mut graph = monitor.graph.borrow_mut();
+                    match key {
+                        Some("default.audio.sink") => graph.default_sink = name,
+                        Some("default.audio.source") => graph.default_source = name,
+                        _ => return 0,
+                    }
+                }
+                monitor.publish();
+                0
+            })
+            .register();
+        self.graph.borrow_mut().metadata.push((metadata, listener));
+    }
+
+    fn bind_card(self: &Rc<Self>, registry: &Registry, obj: &GlobalObject<&DictRef>) {
+        let Ok(device) = registry.bind::<PwDevice, _>(obj) else {
+            return;
+        };
+        let weak = Rc::downgrade(self);
+        let id = obj.id;
+        let listener = device
+            .add_listener_local()
+            .param(move |_, _, _, _, param| {
+                let (Some(monitor), Some(route)) = (weak.upgrade(), param.and_then(parse_route))
+                else {
+                    return;
+                };
+                if let Some(card) = monitor.graph.borrow_mut().cards.get_mut(&id) {
+                    card.routes
+                        .retain(|r| (r.device, r.direction) != (route.device, route.direction));
+                    card.routes.push(route);
+                }
+                monitor.publish();
+            })
+            .register();
+        device.subscribe_params(&[ParamType::Route]);
+
+        self.graph.borrow_mut().cards.insert(
+            id,
+            Card {
+                routes: vec![],
+                _device: device,
+                _listener: listener,
+            },
+        );
+    }
+
+    fn bind_node(
+        self: &Rc<Self>,
+        registry: &Registry,
+        obj: &GlobalObject<&DictRef>,
+        props: &DictRef,
+        class: Class,
+    ) {
+        let Some(name) = props.get("node.name") else {
+            return;
+        };
+        let Ok(node) = registry.bind::<Node, _>(obj) else {
+            return;
+        };
+
+        let weak = Rc::downgrade(self);
+        let id = obj.id;
+        let listener = node
+            .add_listener_local()
+            .param(move |_, _, _, _, param| {
+                let (Some(monitor), Some(param)) = (weak.upgrade(), param) else {
+                    return;
+                };
+                let (volume, mute) = parse_props(param);
+                if let Some(entry) = monitor.graph.borrow_mut().nodes.get_mut(&id) {
+                    if volume.is_some() {
+                        entry.volume = volume;
+                    }
+                    if let Some(mute) = mute {
+                        entry.mute = mute;
+                    }
+                }
+                monitor.publish();
+            })
+            .register();
+        node.subscribe_params(&[ParamType::Props]);
+
+        let bus = props
+            .get("device.bus")
+            .or_else(|| (props.get("device.api") == Some("bluez5")).then_some("bluetooth"));
+        let device = Device {
+            description: props.get("node.description").map(ToString::to_string),
+            // filled in from the routes of the card
+            port: None,
+            form_factor: props.get("device.form_factor").map(ToString::to_string),
+            bus: bus.map(ToString::to_string),
+        };
+
+        self.graph.borrow_mut().nodes.insert(
+            id,
+            Entry {
+                name: name.to_string(),
+                class,
+                device,
+                card: props.get("device.id").and_then(|v| v.parse().ok()).zip(
+                    props
+                        .get("card.profile.device")
+                        .and_then(|v| v.parse().ok()),
+                ),
+                volume: None,
+                mute: false,
+                node,
+                _listener: listener,
+            },
+        );
+    }
+
+    fn global_remove(&self, id: u32) {
+        let removed = {
+            let mut graph = self.graph.borrow_mut();
+            let node = graph.nodes.remove(&id).is_some();
+            let stream = graph.streams.remove(&id);
+            let card = graph.cards.remove(&id).is_some();
+            node || stream || card
+        };
+        if removed {
+            self.publish();
+        }
+    }
+
+    /// Applies `action` to the default sink or source by setting its `Props`
+    fn apply(&self, target: Target, action: Action, max: u32) {
+        let graph = self.graph.borrow();
+        let class = match target {
+            Target::Sink => Class::Sink,
+            Target::Source => Class::Source,
+        };
+        let Some(entry) = graph.default_node(class) else {
+            return;
+        };
+
+        let property = if let Action::ToggleMute = action {
+            Property::new(spa::sys::SPA_PROP_mute, Value::Bool(!entry.mute))
+        } else {
+            let Some(volume) = entry.volume else {
+                return;
+            };
+            Property::new(
+                spa::sys::SPA_PROP_channelVolumes,
+                Value::ValueArray(ValueArray::Float(to_linear(&adjust(volume, action, max)))),
+            )
+        };
+
+        let object = Value::Object(Object {
+            type_: spa::sys::SPA_TYPE_OBJECT_Props,
+            id: spa::sys::SPA_PARAM_Props,
+            properties: vec![property],
+        });
+        let Ok((cursor, _)) = PodSerializer::serialize(std::io::Cursor::new(vec![]), &object)
+        else {
+            return;
+        };
+        let bytes = cursor.into_inner();
+        if let Some(pod) = Pod::from_bytes(&bytes) {
+            entry.node.set_param(ParamType::Props, 0, pod);
+        }
+    }
+}
+
+/// `node.name` from a `default.audio.*` metadata value like `{"name":"alsa_output.pci-..."}`
+fn node_name(value: &str) -> Option<String> {
+    value
+        .split('"')
+        .skip_while(|s| *s != "name")
+        .nth(2)
+        .map(ToString::to_string)
+}
+
+/// `PipeWire` volumes are linear, map them onto the same cubic scale `libpulse` uses
+fn from_linear(linear: &[f32]) -> ChannelVolumes {
+    let mut volume = ChannelVolumes::default();
+    volume.set_len(
+        u8::try_from(linear.len())
+            .unwrap_or(ChannelVolumes::CHANNELS_MAX)
+            .min(ChannelVolumes::CHANNELS_MAX),
+    );
+    for (v, l) in volume.get_mut().iter_mut().zip(linear) {
+        *v = Volume::from(VolumeLinear(f64::from(*l)));
+    }
+    volume
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn to_linear(volume: &ChannelVolumes) -> Vec<f32> {
+    volume
+        .get()
+        .iter()
+        .map(|v| VolumeLinear::from(*v).0 as f32)
+        .collect()
+}
+
+/// Channel volumes and mute from a `Props` param, either may be missing
+fn parse_props(pod: &Pod) -> (Option<ChannelVolumes>, Option<bool>) {
+    let Ok((_, Value::Object(object))) = PodDeserializer::deserialize_any_from(pod.as_bytes())
+    else {
+        return (None, None);
+    };
+
+    let mut volume = None;
+    let mut mute = None;
+    for p in object.properties {
+        match p.value {
+            Value::ValueArray(ValueArray::Float(v))
+                if p.key == spa::sys::SPA_PROP_channelVolumes =>
+            {
+                volume = Some(from_linear(&v));
+            }
+            Value::Bool(m) if p.key == spa::sys::SPA_PROP_mute => mute = Some(m),
+            _ => (),
+        }
+    }
+    (volume, mute)
+}
+
+/// The device index, direction, name and description of a `Route` param
+fn parse_route(pod: &Pod) -> Option<Route> {
+    let Ok((_, Value::Object(object))) = PodDeserializer::deserialize_any_from(pod.as_bytes())
+    else {
+        return None;
+    };
+
+    let (mut device, mut direction, mut name, mut description) = (None, None, None, None);
+    for p in object.properties {
+        match p.value {
+            Value::Int(v) if p.key == spa::sys::SPA_PARAM_ROUTE_device => device = Some(v),
+            Value::Id(Id(v)) if p.key == spa::sys::SPA_PARAM_ROUTE_direction => {
+                direction = Some(v);
+            }
+            Value::String(v) if p.key == spa::sys::SPA_PARAM_ROUTE_name => name = Some(v),
+            Value::String(v) if p.key == spa::sys::SPA_PARAM_ROUTE_description => {
+                description = Some(v);
+            }
+            _ => (),
+        }
+    }
+    Some(Route {
+        device: device?,
+        direction: direction?,
+        name: name?,
+        description,
+    })
+}
+
+/// Runs the `PipeWire` main loop, `ready` gets the result of connecting
+fn run(
+    state: Arc<RwLock<Audio>>,
+    connected: &Arc<AtomicBool>,
+    redraw: mpsc::Sender<()>,
+    commands: pipewire::channel::Receiver<Command>,
+    ready: &mpsc::Sender<Result<(), anyhow::Error>>,
+) {
+    let setup = || -> Result<_, anyhow::Error> {
+        pipewire::init();
+        let mainloop = MainLoopRc::new(None)?;
+        let context = ContextRc::new(&mainloop, None)?;
+        let core = context.connect_rc(None)?;
+        let registry = core.get_registry_rc()?;
+        Ok((mainloop, context, core, registry))
+    };
+    let (mainloop, _context, core, registry) = match setup() {
+        Ok(v) => v,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    // the core reports errors on itself when the server goes away
+    let _core_listener = core
+        .add_listener_local()
+        .error({
+            let mainloop = mainloop.downgrade();
+            let connected = connected.clone();
+            move |id, _, _, message| {
+                if id == PW_ID_CORE {
+                    eprintln!("pipewire connection lost: {message}");
+                    connected.store(false, Ordering::Relaxed);
+                    if let Some(mainloop) = mainloop.upgrade() {
+                        mainloop.quit();
+                    }
+                }
+            }
+        })
+        .register();
+
+    let monitor = Rc::new(Monitor {
+        graph: RefCell::new(Graph::default()),
+        state,
+        redraw,
+    });
+
+    let _registry_listener = registry
+        .add_listener_local()
+        .global({
+            let monitor = Rc::downgrade(&monitor);
+            let registry = registry.downgrade();
+            move |obj| {
+                if let (Some(monitor), Some(registry)) = (monitor.upgrade(), registry.upgrade()) {
+                    monitor.global(&registry, obj);
+                }
+            }
+        })
+        .global_remove({
+            let monitor = Rc::downgrade(&monitor);
+            move |id| {
+                if let Some(monitor) = monitor.upgrade() {
+                    monitor.global_remove(id);
+                }
+            }
+        })
+        .register();
+
+    let _commands = commands.attach(mainloop.loop_(), {
+        let monitor = Rc::downgrade(&monitor);
+        let mainloop = mainloop.downgrade();
+        move |command| match command {
+            Command::Apply(target, action, max) => {
+                if let Some(monitor) = monitor.upgrade() {
+                    monitor.apply(target, action, max);
+                }
+            }
+            Command::Quit => {
+                if let Some(mainloop) = mainloop.upgrade() {
+                    mainloop.quit();
+                }
+            }
+        }
+    });
+
+    // the server announces all its objects before answering a sync, afterwards we know whether
+    // it serves audio at all or e.g. only screen sharing next to a PulseAudio server
+    let pending = match core.sync(0) {
+        Ok(v) => v,
+        Err(e) => {
+            let _ = ready.send(Err(e.into()));
+            return;
+        }
+    };
+    let synced = Rc::new(Cell::new(false));
+    let _sync_listener = core
+        .add_listener_local()
+        .done({
+            let synced = synced.clone();
+            let mainloop = mainloop.downgrade();
+            move |id, seq| {
+                if id == PW_ID_CORE && seq == pending {
+                    synced.set(true);
+                    if let Some(mainloop) = mainloop.upgrade() {
+                        mainloop.quit();
+                    }
+                }
+            }
+        })
+        .register();
+    mainloop.run();
+
+    let audio = {
+        let graph = monitor.graph.borrow();
+        !graph.nodes.is_empty() || !graph.metadata.is_empty()
+    };
+    if !synced.get() || !audio {
+        let _ = ready.send(Err(anyhow::anyhow!("pipewire does not serve audio")));
+        return;
+    }
+
+    connected.store(true, Ordering::Relaxed);
+    let _ = ready.send(Ok(()));
+    mainloop.run();
+    connected.store(false, Ordering::Relaxed);
+}
+
+/// Connection to a `PipeWire` server, its objects all live on a thread of their own
+pub(super) struct Connection {
+    tx: pipewire::channel::Sender<Command>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Connection {
+    pub(super) fn new(
+        state: Arc<RwLock<Audio>>,
+        connected: Arc<AtomicBool>,
+        redraw: mpsc::Sender<()>,
+    ) -> Result<Self, anyhow::Error> {
+        let (tx, rx) = pipewire::channel::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = std::thread::spawn(move || run(state, &connected, redraw, rx, &ready_tx));
+
+        match ready_rx.recv_timeout(CONNECT_TIMEOUT) {
+            Ok(Ok(())) => Ok(Self {
+                tx,
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                Err(e)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                let _ = thread.join();
+                Err(anyhow::anyhow!("pipewire thread exited"))
+            }
+            // the thread stops on its own once the main loop runs, we do not wait for it
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let _ = tx.send(Command::Quit);
+                Err(anyhow::anyhow!("pipewire did not answer"))
+            }
+        }
+    }
+
+    pub(super) fn apply(&self, target: Target, action: Action, max: u32) {
+        let _ = self.tx.send(Command::Apply(target, action, max));
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.tx.send(Command::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_node_name() {
+        assert_eq!(
+            node_name(r#"{"name":"alsa_output.pci-0000_00_1f.3.analog-stereo"}"#).as_deref(),
+            Some("alsa_output.pci-0000_00_1f.3.analog-stereo")
+        );
+        assert_eq!(
+            node_name(r#"{ "name": "bluez_output.00_11_22_33_44_55.1" }"#).as_deref(),
+            Some("bluez_output.00_11_22_33_44_55.1")
+        );
+        assert_eq!(node_name("{}"), None);
+    }
+
+    #[test]
+    fn linear_volume_round_trip() {
+        let volume = from_linear(&[0.5, 1.0]);
+        assert_eq!(volume.len(), 2);
+        let linear = to_linear(&volume);
+        assert!((linear[0] - 0.5).abs() < 0.01);
+        assert!((linear[1] - 1.0).abs() < 0.01);
+    }
+}
@@ -1,39 +1,237 @@
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock, mpsc};
+use std::time::{Duration, Instant};
 
 use libpulse_binding::{
     callbacks::ListResult,
     context::{
         Context, FlagSet, State,
-        introspect::{ServerInfo, SinkInfo},
-        subscribe::{Facility, InterestMaskSet},
+        introspect::{Introspector, ServerInfo, SinkInfo, SourceInfo},
+        subscribe::{Facility, InterestMaskSet, Operation},
     },
     mainloop::threaded::Mainloop,
     proplist::{Proplist, properties},
-    volume::Volume,
+    volume::{ChannelVolumes, Volume},
 };
 
+use super::{Alsa, Block};
 use crate::shared::Shared;
 
-#[derive(Debug)]
-struct TxState {
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_mins(1);
+
+const DEFAULT_STEP: u32 = 5;
+const DEFAULT_MAX_VOLUME: u32 = 100;
+const DEFAULT_ICONS: &[(&str, &str)] =
+    &[("headphone", "🎧"), ("headset", "🎧"), ("bluetooth", "ᛒ")];
+
+#[derive(Debug, Default)]
+pub(super) struct TxState {
     pub volume: u32,
     pub mute: bool,
+    // percentage of every channel, in channel map order
+    pub channels: Vec<u32>,
+    // any channel is above `Volume::NORMAL`
+    pub amplified: bool,
+}
+
+impl TxState {
+    pub(super) fn new(volume: &ChannelVolumes, mute: bool) -> Self {
+        Self {
+            volume: percent(volume.avg()),
+            mute,
+            channels: volume.get().iter().map(|v| percent(*v)).collect(),
+            amplified: volume.max() > Volume::NORMAL,
+        }
+    }
+}
+
+/// Where the default sink sends its audio
+#[derive(Debug, Default, Clone)]
+pub(super) struct Device {
+    pub(super) description: Option<String>,
+    // name and description of the active port, e.g. `analog-output-headphones`
+    pub(super) port: Option<(String, Option<String>)>,
+    // `device.form_factor`, e.g. `headphone`, `headset` or `speaker`
+    pub(super) form_factor: Option<String>,
+    // `device.bus`, e.g. `bluetooth`, `usb` or `pci`
+    pub(super) bus: Option<String>,
+}
+
+impl Device {
+    fn from_sink(item: &SinkInfo<'_>) -> Self {
+        Self {
+            description: item.description.as_ref().map(ToString::to_string),
+            port: item.active_port.as_ref().and_then(|p| {
+                Some((
+                    p.name.as_ref()?.to_string(),
+                    p.description.as_ref().map(ToString::to_string),
+                ))
+            }),
+            form_factor: item.proplist.get_str(properties::DEVICE_FORM_FACTOR),
+            bus: item.proplist.get_str(properties::DEVICE_BUS),
+        }
+    }
+
+    /// Active port description, falling back to the sink description
+    fn name(&self) -> Option<&str> {
+        self.port
+            .as_ref()
+            .and_then(|(_, d)| d.as_deref())
+            .or(self.description.as_deref())
+    }
+
+    /// Whether `key` names the form factor or bus exactly, or is part of the active port name
+    fn matches(&self, key: &str) -> bool {
+        self.form_factor.as_deref() == Some(key)
+            || self.bus.as_deref() == Some(key)
+            || self.port.as_ref().is_some_and(|(n, _)| n.contains(key))
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Audio {
+    pub(super) sink: TxState,
+    pub(super) device: Device,
+    // `None` if there is no default source or it is just a monitor
+    pub(super) source: Option<TxState>,
+    // any application has a stream on any source
+    pub(super) recording: bool,
 }
 
 enum TxMessage {
     DefaultSinkChange(String),
-    SinkValueChange { val: TxState, name: String },
+    SinkValueChange {
+        val: TxState,
+        device: Device,
+        name: String,
+    },
+    DefaultSourceChange(String),
+    SourceValueChange {
+        val: Option<TxState>,
+        name: String,
+    },
+    RecordingChange(bool),
+    Quit,
 }
 
-pub struct Pulse {
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Target {
+    Sink,
+    Source,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Action {
+    Up(u32),
+    Down(u32),
+    Set(u32),
+    ToggleMute,
+}
+
+fn from_percent(percent: u32) -> Volume {
+    let v = u64::from(Volume::NORMAL.0) * u64::from(percent) / 100;
+    Volume(u32::try_from(v).unwrap_or(Volume::MAX.0).min(Volume::MAX.0))
+}
+
+/// New volume after `action`, keeping the balance between channels
+pub(super) fn adjust(mut volume: ChannelVolumes, action: Action, max: u32) -> ChannelVolumes {
+    match action {
+        Action::Up(p) => {
+            volume.inc_clamp(from_percent(p), from_percent(max));
+        }
+        Action::Down(p) => {
+            volume.decrease(from_percent(p));
+        }
+        Action::Set(p) => {
+            volume.scale(from_percent(p.min(max)));
+        }
+        Action::ToggleMute => (),
+    }
+    volume
+}
+
+/// Speaker symbol for `volume` in percent
+pub(super) const fn speaker(volume: u32) -> &'static str {
+    if volume > 70 {
+        "🔊"
+    } else if volume > 30 {
+        "🔉"
+    } else {
+        "🔈"
+    }
+}
+
+fn percent(volume: Volume) -> u32 {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let volume = ((volume.0 as f32 / Volume::NORMAL.0 as f32) * 100.).round() as u32;
+    volume
+}
+
+fn tx_server(tx: &mpsc::Sender<TxMessage>, result: &ServerInfo<'_>) {
+    if let Some(n) = &result.default_sink_name {
+        let _ = tx.send(TxMessage::DefaultSinkChange(n.to_string()));
+    }
+    if let Some(n) = &result.default_source_name {
+        let _ = tx.send(TxMessage::DefaultSourceChange(n.to_string()));
+    }
+}
+
+fn tx_sink(tx: &mpsc::Sender<TxMessage>, result: &ListResult<&SinkInfo<'_>>) {
+    if let ListResult::Item(item) = result
+        && let Some(name) = &item.name
+    {
+        let _ = tx.send(TxMessage::SinkValueChange {
+            val: TxState::new(&item.volume, item.mute),
+            device: Device::from_sink(item),
+            name: name.to_string(),
+        });
+    }
+}
+
+fn tx_source(tx: &mpsc::Sender<TxMessage>, result: &ListResult<&SourceInfo<'_>>) {
+    if let ListResult::Item(item) = result
+        && let Some(name) = &item.name
+    {
+        let _ = tx.send(TxMessage::SourceValueChange {
+            val: item
+                .monitor_of_sink
+                .is_none()
+                .then(|| TxState::new(&item.volume, item.mute)),
+            name: name.to_string(),
+        });
+    }
+}
+
+fn tx_recording(introspect: &Introspector, tx: mpsc::Sender<TxMessage>) {
+    let mut streams = 0;
+    introspect.get_source_output_info_list(move |res| match res {
+        ListResult::Item(_) => streams += 1,
+        ListResult::End => {
+            let _ = tx.send(TxMessage::RecordingChange(streams > 0));
+        }
+        ListResult::Error => (),
+    });
+}
+
+/// One connection to the server, a new one is created whenever the server goes away
+struct Connection {
     mainloop: Shared<Mainloop>,
     context: Shared<Context>,
-
-    state: Arc<RwLock<TxState>>,
+    tx: Option<mpsc::Sender<TxMessage>>,
 }
 
-impl Pulse {
-    pub fn new() -> Result<Self, anyhow::Error> {
+impl Connection {
+    fn new(
+        state: Arc<RwLock<Audio>>,
+        connected: Arc<AtomicBool>,
+        redraw: mpsc::Sender<()>,
+    ) -> Result<Self, anyhow::Error> {
         let mut proplist =
             Proplist::new().ok_or_else(|| anyhow::anyhow!("Failed to init Proplist"))?;
         proplist
@@ -48,29 +246,33 @@ impl Pulse {
                 .ok_or_else(|| anyhow::anyhow!("Failed to init Context"))?,
         );
 
-        let s = Self {
+        let mut s = Self {
             mainloop,
             context,
-            state: Arc::new(RwLock::new(TxState {
-                volume: 0,
-                mute: false,
-            })),
+            tx: None,
         };
-        s.connect()?;
+        s.connect(connected)?;
+        s.tx = Some(s.subscribe(state, redraw));
 
         Ok(s)
     }
 
-    fn connect(&self) -> Result<(), anyhow::Error> {
+    fn connect(&self, connected: Arc<AtomicBool>) -> Result<(), anyhow::Error> {
         let mut mainloop = self.mainloop.borrow_mut();
         let mut ctx = self.context.borrow_mut();
 
         let mainloop_shr_ref = self.mainloop.clone_rc();
         let ctx_shr_ref = self.context.clone_rc();
 
+        // stays installed after we are ready so we notice when the server goes away
         ctx.set_state_callback(Some(Box::new(move || {
             match unsafe { (*ctx_shr_ref.as_ptr()).get_state() } {
-                State::Ready | State::Failed | State::Terminated => unsafe {
+                State::Ready => unsafe {
+                    connected.store(true, Ordering::Relaxed);
+                    (*mainloop_shr_ref.as_ptr()).signal(false);
+                },
+                State::Failed | State::Terminated => unsafe {
+                    connected.store(false, Ordering::Relaxed);
                     (*mainloop_shr_ref.as_ptr()).signal(false);
                 },
                 _ => {}
@@ -85,15 +287,12 @@ impl Pulse {
         loop {
             match ctx.get_state() {
                 State::Ready => {
-                    ctx.set_state_callback(None);
                     mainloop.unlock();
                     break;
                 }
                 State::Failed | State::Terminated => {
-                    eprintln!("Context state failed/terminated, quitting...");
                     mainloop.unlock();
-                    mainloop.stop();
-                    panic!("Pulse session terminated.");
+                    return Err(anyhow::anyhow!("Context state failed/terminated"));
                 }
                 _ => {
                     mainloop.wait();
@@ -101,44 +300,14 @@ impl Pulse {
             }
         }
 
-        drop(ctx);
-        drop(mainloop);
-
-        self.subscribe();
-
         Ok(())
     }
 
-    fn subscribe(&self) {
-        fn tx_server(tx: &mpsc::Sender<TxMessage>, result: &ServerInfo<'_>) {
-            if let Some(n) = &result.default_sink_name {
-                tx.send(TxMessage::DefaultSinkChange(n.to_string()))
-                    .unwrap();
-            }
-        }
-
-        fn tx_sink(tx: &mpsc::Sender<TxMessage>, result: &ListResult<&SinkInfo<'_>>) {
-            if let ListResult::Item(item) = result
-                && let Some(name) = &item.name
-            {
-                #[allow(
-                    clippy::cast_possible_truncation,
-                    clippy::cast_sign_loss,
-                    clippy::cast_precision_loss
-                )]
-                let volume =
-                    ((item.volume.avg().0 as f32 / Volume::NORMAL.0 as f32) * 100.).round() as u32;
-                tx.send(TxMessage::SinkValueChange {
-                    val: TxState {
-                        volume,
-                        mute: item.mute,
-                    },
-                    name: name.to_string(),
-                })
-                .unwrap();
-            }
-        }
-
+    fn subscribe(
+        &self,
+        state: Arc<RwLock<Audio>>,
+        redraw: mpsc::Sender<()>,
+    ) -> mpsc::Sender<TxMessage> {
         let mut mainloop = self.mainloop.borrow_mut();
         let mut ctx = self.context.borrow_mut();
         mainloop.lock();
@@ -148,29 +317,45 @@ impl Pulse {
 
         let tx2 = tx.clone();
         introspect.get_sink_info_by_name("@DEFAULT_SINK@", move |res| tx_sink(&tx2, &res));
+        let tx2 = tx.clone();
+        introspect.get_source_info_by_name("@DEFAULT_SOURCE@", move |res| {
+            tx_source(&tx2, &res);
+        });
+        tx_recording(&introspect, tx.clone());
 
         let tx2 = tx.clone();
-        ctx.subscribe(InterestMaskSet::SERVER | InterestMaskSet::SINK, |_| ());
+        ctx.subscribe(
+            InterestMaskSet::SERVER
+                | InterestMaskSet::SINK
+                | InterestMaskSet::SOURCE
+                | InterestMaskSet::SOURCE_OUTPUT,
+            |_| (),
+        );
         ctx.set_subscribe_callback(Some(Box::new(move |fac, op, index| {
             let tx2 = tx2.clone();
 
-            if op == Some(libpulse_binding::context::subscribe::Operation::Changed) {
-                match fac {
-                    Some(Facility::Server) => {
-                        introspect.get_server_info(move |res| tx_server(&tx2, res));
-                    }
-                    Some(Facility::Sink) => {
-                        introspect.get_sink_info_by_index(index, move |res| tx_sink(&tx2, &res));
-                    }
-                    _ => (),
+            match (fac, op) {
+                (Some(Facility::Server), Some(Operation::Changed)) => {
+                    introspect.get_server_info(move |res| tx_server(&tx2, res));
                 }
+                (Some(Facility::Sink), Some(Operation::Changed)) => {
+                    introspect.get_sink_info_by_index(index, move |res| tx_sink(&tx2, &res));
+                }
+                (Some(Facility::Source), Some(Operation::Changed)) => {
+                    introspect.get_source_info_by_index(index, move |res| tx_source(&tx2, &res));
+                }
+                (Some(Facility::SourceOutput), Some(Operation::New | Operation::Removed)) => {
+                    tx_recording(&introspect, tx2);
+                }
+                _ => (),
             }
         })));
 
-        let state = self.state.clone();
+        let quit = tx.clone();
         let introspect = ctx.introspect();
         std::thread::spawn(move || {
             let mut default_sink_name: Option<String> = None;
+            let mut default_source_name: Option<String> = None;
             loop {
                 let tx = tx.clone();
                 let state = state.clone();
@@ -183,52 +368,378 @@ impl Pulse {
                             move |res| tx_sink(&tx, &res),
                         );
                     }
-                    Ok(TxMessage::SinkValueChange { val, name }) => {
+                    Ok(TxMessage::SinkValueChange { val, device, name }) => {
                         if default_sink_name.is_none() {
                             default_sink_name = Some(name);
                         } else if default_sink_name != Some(name) {
                             continue;
                         }
                         if let Ok(mut w) = state.write() {
-                            *w = val;
+                            w.sink = val;
+                            w.device = device;
+                        }
+                        let _ = redraw.send(());
+                    }
+                    Ok(TxMessage::DefaultSourceChange(v)) => {
+                        default_source_name = Some(v);
+                        introspect.get_source_info_by_name(
+                            default_source_name.as_ref().unwrap(),
+                            move |res| tx_source(&tx, &res),
+                        );
+                    }
+                    Ok(TxMessage::SourceValueChange { val, name }) => {
+                        if default_source_name.is_none() {
+                            default_source_name = Some(name);
+                        } else if default_source_name != Some(name) {
+                            continue;
+                        }
+                        if let Ok(mut w) = state.write() {
+                            w.source = val;
                         }
+                        let _ = redraw.send(());
                     }
-                    Err(_) => (),
+                    Ok(TxMessage::RecordingChange(v)) => {
+                        if let Ok(mut w) = state.write() {
+                            w.recording = v;
+                        }
+                        let _ = redraw.send(());
+                    }
+                    Ok(TxMessage::Quit) | Err(_) => break,
                 }
             }
         });
 
+        mainloop.unlock();
+        quit
+    }
+
+    /// Applies `action` to the default sink or source
+    fn apply(&self, target: Target, action: Action, max: u32) {
+        let mut mainloop = self.mainloop.borrow_mut();
+        let ctx = self.context.borrow_mut();
+        mainloop.lock();
+
+        let introspect = ctx.introspect();
+        let mut setter = ctx.introspect();
+        match target {
+            Target::Sink => {
+                introspect.get_sink_info_by_name("@DEFAULT_SINK@", move |res| {
+                    if let ListResult::Item(item) = res
+                        && let Some(name) = &item.name
+                    {
+                        if let Action::ToggleMute = action {
+                            setter.set_sink_mute_by_name(name, !item.mute, None);
+                        } else {
+                            let volume = adjust(item.volume, action, max);
+                            setter.set_sink_volume_by_name(name, &volume, None);
+                        }
+                    }
+                });
+            }
+            Target::Source => {
+                introspect.get_source_info_by_name("@DEFAULT_SOURCE@", move |res| {
+                    if let ListResult::Item(item) = res
+                        && let Some(name) = &item.name
+                    {
+                        if let Action::ToggleMute = action {
+                            setter.set_source_mute_by_name(name, !item.mute, None);
+                        } else {
+                            let volume = adjust(item.volume, action, max);
+                            setter.set_source_volume_by_name(name, &volume, None);
+                        }
+                    }
+                });
+            }
+        }
+
         mainloop.unlock();
     }
 
     fn cleanup(&self) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(TxMessage::Quit);
+        }
+
         let mut ctx = self.context.borrow_mut();
         let mut mainloop = self.mainloop.borrow_mut();
 
+        // the callbacks hold references to the context, drop them so it can be freed
+        mainloop.lock();
+        ctx.set_state_callback(None);
+        ctx.set_subscribe_callback(None);
         ctx.disconnect();
+        mainloop.unlock();
         mainloop.stop();
     }
 }
 
-impl Drop for Pulse {
+impl Drop for Connection {
     fn drop(&mut self) {
         self.cleanup();
     }
 }
 
-impl super::Block for Pulse {
+/// Whichever server we are talking to, a native `PipeWire` one is preferred when built in
+enum Backend {
+    Pulse(Connection),
+    #[cfg(feature = "pipewire")]
+    PipeWire(super::pipewire::Connection),
+}
+
+impl Backend {
+    fn new(
+        state: Arc<RwLock<Audio>>,
+        connected: Arc<AtomicBool>,
+        redraw: mpsc::Sender<()>,
+    ) -> Result<Self, anyhow::Error> {
+        #[cfg(feature = "pipewire")]
+        {
+            if let Ok(conn) =
+                super::pipewire::Connection::new(state.clone(), connected.clone(), redraw.clone())
+            {
+                return Ok(Self::PipeWire(conn));
+            }
+        }
+        Connection::new(state, connected, redraw).map(Self::Pulse)
+    }
+
+    fn apply(&self, target: Target, action: Action, max: u32) {
+        match self {
+            Self::Pulse(conn) => conn.apply(target, action, max),
+            #[cfg(feature = "pipewire")]
+            Self::PipeWire(conn) => conn.apply(target, action, max),
+        }
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
+pub struct Pulse {
+    conn: RefCell<Option<Backend>>,
+    connected: Arc<AtomicBool>,
+    // when to try to reconnect next and how long to wait after that
+    retry: Cell<(Instant, Duration)>,
+
+    state: Arc<RwLock<Audio>>,
+    redraw: mpsc::Sender<()>,
+    microphone: bool,
+    recording: bool,
+    step: u32,
+    max_volume: u32,
+    icons: Vec<(String, String)>,
+    device: bool,
+    channels: bool,
+    alsa: Option<Alsa>,
+}
+
+impl Pulse {
+    /// Never fails, if the server is not running yet we keep trying to connect in `run`
+    pub fn new(redraw: mpsc::Sender<()>) -> Self {
+        let s = Self {
+            conn: RefCell::new(None),
+            connected: Arc::new(AtomicBool::new(false)),
+            retry: Cell::new((Instant::now(), MIN_BACKOFF)),
+
+            state: Arc::new(RwLock::new(Audio::default())),
+            redraw,
+            microphone: false,
+            recording: false,
+            step: DEFAULT_STEP,
+            max_volume: DEFAULT_MAX_VOLUME,
+            icons: DEFAULT_ICONS
+                .iter()
+                .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+                .collect(),
+            device: false,
+            channels: false,
+            alsa: None,
+        };
+        s.reconnect();
+        s
+    }
+
+    /// Also shows volume and mute state of the default source, unless it is a monitor
+    pub const fn microphone(mut self, microphone: bool) -> Self {
+        self.microphone = microphone;
+        self
+    }
+
+    /// Marks when any application is recording from any source
+    pub const fn recording(mut self, recording: bool) -> Self {
+        self.recording = recording;
+        self
+    }
+
+    /// Percentage `up` and `down` change the volume by if no amount is given
+    pub const fn step(mut self, step: u32) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Percentage `up` and `set` never go above, `Volume::NORMAL` is 100
+    pub const fn max_volume(mut self, max_volume: u32) -> Self {
+        self.max_volume = max_volume;
+        self
+    }
+
+    /// Icons replacing the speaker symbol, keyed by form factor, bus or part of the active port
+    /// name, the first match wins
+    pub fn icons(mut self, icons: &[(&str, &str)]) -> Self {
+        self.icons = icons
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect();
+        self
+    }
+
+    /// Also shows the description of the active port or default sink
+    pub const fn device(mut self, device: bool) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Shows every channel, e.g. `40%/60%` for left and right, when they are not all the same
+    pub const fn channels(mut self, channels: bool) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Shows and controls an ALSA mixer instead while no sound server is running
+    pub fn fallback(mut self, alsa: Alsa) -> Self {
+        self.alsa = Some(alsa);
+        self
+    }
+
+    /// Volume of `state` as shown in the bar, marked when it is amplified above 100%
+    fn volume(&self, state: &TxState) -> String {
+        let mut volume = if self.channels && state.channels.iter().any(|c| *c != state.volume) {
+            state
+                .channels
+                .iter()
+                .map(|c| format!("{c}%"))
+                .collect::<Vec<_>>()
+                .join("/")
+        } else {
+            format!("{}%", state.volume)
+        };
+        if state.amplified {
+            volume.push('❗');
+        }
+        volume
+    }
+
+    /// `[mic] up [n]|down [n]|set <n>|mute`, without `mic` the default sink is changed
+    fn control(&self, args: &[&str]) -> Result<(), anyhow::Error> {
+        let (target, args) = match args {
+            ["mic", args @ ..] => (Target::Source, args),
+            _ => (Target::Sink, args),
+        };
+        let action = match args {
+            ["up"] => Action::Up(self.step),
+            ["up", n] => Action::Up(n.parse()?),
+            ["down"] => Action::Down(self.step),
+            ["down", n] => Action::Down(n.parse()?),
+            ["set", n] => Action::Set(n.parse()?),
+            ["mute"] => Action::ToggleMute,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "usage: pulse [mic] up [n]|down [n]|set <n>|mute"
+                ));
+            }
+        };
+
+        let conn = self.conn.borrow();
+        match (conn.as_ref(), &self.alsa, target) {
+            (Some(conn), _, _) if self.connected.load(Ordering::Relaxed) => {
+                conn.apply(target, action, self.max_volume);
+                Ok(())
+            }
+            (_, Some(alsa), Target::Sink) => alsa.control_command("pulse", args),
+            _ => Err(anyhow::anyhow!("not connected")),
+        }
+    }
+
+    /// Replaces the connection after the server went away, backing off while it stays away
+    fn reconnect(&self) {
+        let (next, backoff) = self.retry.get();
+        if Instant::now() < next {
+            return;
+        }
+
+        // drop the old connection first, so we never run two mainloops at once
+        self.conn.borrow_mut().take();
+        match Backend::new(
+            self.state.clone(),
+            self.connected.clone(),
+            self.redraw.clone(),
+        ) {
+            Ok(conn) => {
+                *self.conn.borrow_mut() = Some(conn);
+                self.retry.set((Instant::now(), MIN_BACKOFF));
+            }
+            Err(e) => {
+                eprintln!("failed to reconnect to the audio server: {e}");
+                self.retry
+                    .set((Instant::now() + backoff, (backoff * 2).min(MAX_BACKOFF)));
+            }
+        }
+    }
+}
+
+impl Block for Pulse {
+    fn command(&self, args: &[&str]) -> Option<Result<(), anyhow::Error>> {
+        let ["pulse", args @ ..] = args else {
+            return None;
+        };
+        Some(self.control(args))
+    }
+
     fn run(&self) -> Result<Option<String>, anyhow::Error> {
+        if !self.connected.load(Ordering::Relaxed) {
+            self.reconnect();
+        }
+        if !self.connected.load(Ordering::Relaxed) {
+            if let Some(alsa) = &self.alsa {
+                return alsa.run();
+            }
+            return Ok(Some("🔈 ✖".into()));
+        }
+        // its watcher would redraw on every change the server makes to the mixer as well
+        if let Some(alsa) = &self.alsa {
+            alsa.close();
+        }
+
         let r = self.state.read().unwrap();
-        if r.mute {
-            return Ok(Some("🔇".into()));
+        let mut out = vec![];
+        if self.recording && r.recording {
+            out.push("🔴".to_string());
         }
-        let symbol = if r.volume > 70 {
-            "🔊"
-        } else if r.volume > 30 {
-            "🔉"
+
+        if r.sink.mute {
+            out.push("🔇".into());
         } else {
-            "🔈"
-        };
-        Ok(Some(format!("{symbol} {}%", r.volume)))
+            let icon = self
+                .icons
+                .iter()
+                .find(|(k, _)| r.device.matches(k))
+                .map(|(_, v)| v.as_str());
+            let symbol = icon.unwrap_or_else(|| speaker(r.sink.volume));
+            out.push(format!("{symbol} {}", self.volume(&r.sink)));
+        }
+        if self.device
+            && let Some(name) = r.device.name()
+        {
+            out.push(name.to_string());
+        }
+
+        if self.microphone
+            && let Some(source) = &r.source
+        {
+            if source.mute {
+                out.push("🎤✖".into());
+            } else {
+                out.push(format!("🎤 {}", self.volume(source)));
+            }
+        }
+        Ok(Some(out.join(" ")))
     }
 }
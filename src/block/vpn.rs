@@ -0,0 +1,126 @@
+use std::cell::Cell;
+use std::time::{Duration, SystemTime};
+
+use super::internet::{Kind, classify, devtype, is_up};
+use crate::netlink;
+
+const DEFAULT_STALE: Duration = Duration::from_mins(3);
+
+const WG_CMD_GET_DEVICE: u8 = 0;
+const WGDEVICE_A_IFNAME: u16 = 2;
+const WGDEVICE_A_PEERS: u16 = 8;
+const WGPEER_A_LAST_HANDSHAKE_TIME: u16 = 6;
+
+/// Latest handshake of all peers in seconds since the epoch, `None` if there never was one.
+/// Querying a device requires `CAP_NET_ADMIN`.
+fn latest_handshake(
+    sock: &netlink::Socket,
+    family: u16,
+    name: &str,
+) -> Result<Option<u64>, anyhow::Error> {
+    let mut attrs = vec![];
+    let mut name = name.as_bytes().to_vec();
+    name.push(0);
+    netlink::push_attr(&mut attrs, WGDEVICE_A_IFNAME, &name);
+
+    let mut latest = None;
+    for m in sock.genl_request(family, WG_CMD_GET_DEVICE, netlink::NLM_F_DUMP, &attrs)? {
+        let Some(peers) = netlink::attr(m.genl_attrs(), WGDEVICE_A_PEERS) else {
+            continue;
+        };
+        for (_, peer) in netlink::attrs(peers) {
+            // struct __kernel_timespec, we only care about the seconds
+            let secs = netlink::attr(peer, WGPEER_A_LAST_HANDSHAKE_TIME)
+                .and_then(|v| Some(u64::from_ne_bytes(v.get(..8)?.try_into().ok()?)))
+                .filter(|v| *v > 0);
+            latest = latest.max(secs);
+        }
+    }
+    Ok(latest)
+}
+
+pub struct Vpn {
+    wireguard: Option<(netlink::Socket, u16)>,
+    stale: Duration,
+    // whether we logged that handshakes can not be read, only done once
+    warned: Cell<bool>,
+}
+
+impl Vpn {
+    pub fn new() -> Self {
+        let wireguard = netlink::Socket::new(libc::NETLINK_GENERIC, 0)
+            .and_then(|s| s.genl_family("wireguard").map(|f| (s, f)));
+
+        Self {
+            wireguard: wireguard.ok(),
+            stale: DEFAULT_STALE,
+            warned: Cell::new(false),
+        }
+    }
+
+    /// Wireguard tunnels whose latest handshake is older than this are shown with a warning
+    pub const fn stale_after(mut self, stale: Duration) -> Self {
+        self.stale = stale;
+        self
+    }
+
+    /// A wireguard tunnel, with a warning if its handshake is stale and `❔` if we can not tell
+    fn wireguard(&self, name: &str) -> String {
+        let handshake = match &self.wireguard {
+            Some((sock, family)) => latest_handshake(sock, *family, name),
+            None => Err(anyhow::anyhow!("wireguard netlink family not found")),
+        };
+        let handshake = match handshake {
+            Ok(v) => v,
+            Err(e) => {
+                // usually EPERM, an unprivileged user is not allowed to ask
+                if !self.warned.replace(true) {
+                    eprintln!("wireguard handshakes not available, showing them as unknown: {e}");
+                }
+                return format!("❔ {name}");
+            }
+        };
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        match handshake.map(|v| now.saturating_sub(Duration::from_secs(v))) {
+            Some(age) if age <= self.stale => format!("🔐 {name}"),
+            Some(age) => format!("⚠️ {name} {}m", age.as_secs() / 60),
+            None => format!("⚠️ {name}"),
+        }
+    }
+}
+
+impl super::Block for Vpn {
+    fn run(&self) -> Result<Option<String>, anyhow::Error> {
+        // `(name, wireguard)`
+        let mut names = vec![];
+        for dev in std::fs::read_dir("/sys/class/net")?.flatten() {
+            let Ok(name) = dev.file_name().into_string() else {
+                continue;
+            };
+            let path = dev.path();
+            if classify(&path) == Some(Kind::Tunnel) && is_up(&path)? {
+                names.push((name, devtype(&path).as_deref() == Some("wireguard")));
+            }
+        }
+        names.sort();
+
+        if names.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            names
+                .iter()
+                .map(|(n, wireguard)| {
+                    if *wireguard {
+                        self.wireguard(n)
+                    } else {
+                        format!("🔐 {n}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        ))
+    }
+}
@@ -1,12 +1,13 @@
 use std::fmt::Display;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicI32, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Deserializer};
 
-// every 4h (timeout 1s)
-const TIMEOUT_TIME: i32 = 60 * 60 * 4;
+// other blocks wake the bar up as well, so these are times rather than runs
+const TIMEOUT_TIME: Duration = Duration::from_hours(4);
+const RETRY_TIME: Duration = Duration::from_hours(1);
 
 pub fn deserialize_number_from_string<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
@@ -60,7 +61,8 @@ pub struct Weather {
     agent: ureq::Agent,
     data: Arc<RwLock<Option<Data>>>,
 
-    timeout: Arc<AtomicI32>,
+    // when to fetch the data again
+    timeout: Arc<Mutex<Instant>>,
 }
 
 fn get_weather_data(agent: &ureq::Agent) -> Result<Option<Data>, anyhow::Error> {
@@ -120,40 +122,44 @@ impl Weather {
             }
         };
         let timeout = if data.is_some() {
-            AtomicI32::new(TIMEOUT_TIME)
+            Instant::now() + TIMEOUT_TIME
         } else {
-            // if first fetch is None try again 60 ticks later
-            AtomicI32::new(60)
+            // if first fetch is None try again a minute later
+            Instant::now() + Duration::from_mins(1)
         };
 
         Self {
             agent,
             data: Arc::new(RwLock::new(data)),
 
-            timeout: Arc::new(timeout),
+            timeout: Arc::new(Mutex::new(timeout)),
         }
     }
 
     fn refresh_data(&self) {
-        self.timeout.fetch_sub(1, Ordering::SeqCst);
-        if self.timeout.load(Ordering::Relaxed) == 0 {
-            let timeout = self.timeout.clone();
-
-            let d = self.data.clone();
-            let agent = self.agent.clone();
-            std::thread::spawn(move || {
-                let new = get_weather_data(&agent).unwrap_or_default();
-                if new.is_none() {
-                    // if refresh data is still None, move refresh time back to 3600 ticks aka 1h
-                    timeout.store(60 * 60, Ordering::Relaxed);
-                    return;
-                }
-
-                timeout.store(TIMEOUT_TIME, Ordering::Relaxed);
-                let mut w = d.write().unwrap();
-                *w = new;
-            });
+        {
+            let mut timeout = self.timeout.lock().unwrap();
+            if Instant::now() < *timeout {
+                return;
+            }
+            // if refresh data is still None, try again in 1h. Also keeps us from fetching twice
+            // while the request is running
+            *timeout = Instant::now() + RETRY_TIME;
         }
+        let timeout = self.timeout.clone();
+
+        let d = self.data.clone();
+        let agent = self.agent.clone();
+        std::thread::spawn(move || {
+            let new = get_weather_data(&agent).unwrap_or_default();
+            if new.is_none() {
+                return;
+            }
+
+            *timeout.lock().unwrap() = Instant::now() + TIMEOUT_TIME;
+            let mut w = d.write().unwrap();
+            *w = new;
+        });
     }
 }
 
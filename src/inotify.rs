@@ -0,0 +1,69 @@
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+const EVENT_HDRLEN: usize = 16;
+const RECV_BUF: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct Event {
+    pub wd: i32,
+    pub mask: u32,
+}
+
+/// Minimal blocking inotify instance, watches can be added while another thread reads
+pub struct Inotify {
+    fd: OwnedFd,
+}
+
+impl Inotify {
+    pub fn new() -> Result<Self, anyhow::Error> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Returns the watch descriptor, the same one again if `path` is already watched
+    pub fn add_watch(&self, path: &Path, mask: u32) -> Result<i32, anyhow::Error> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(wd)
+    }
+
+    /// Blocks until there are events
+    pub fn read(&self) -> Result<Vec<Event>, anyhow::Error> {
+        let mut buf = vec![0u8; RECV_BUF];
+        let r = loop {
+            let r = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if r >= 0 {
+                break r;
+            }
+            let e = std::io::Error::last_os_error();
+            if e.kind() != std::io::ErrorKind::Interrupted {
+                return Err(e.into());
+            }
+        };
+        buf.truncate(usize::try_from(r)?);
+
+        let mut out = vec![];
+        let mut rest = &buf[..];
+        while rest.len() >= EVENT_HDRLEN {
+            // followed by the name of the file, which we do not need
+            let len = usize::try_from(u32::from_ne_bytes(rest[12..16].try_into()?))?;
+            out.push(Event {
+                wd: i32::from_ne_bytes(rest[0..4].try_into()?),
+                mask: u32::from_ne_bytes(rest[4..8].try_into()?),
+            });
+            rest = rest.get(EVENT_HDRLEN + len..).unwrap_or_default();
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+// for clients to send their command and for blocks to handle it
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A command sent with `ministatus <block> <args...>`, answered through `reply`
+pub struct Request {
+    pub args: Vec<String>,
+    pub reply: mpsc::Sender<Result<(), String>>,
+}
+
+fn path() -> Result<PathBuf, anyhow::Error> {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(dir).join("ministatus.sock"));
+    }
+    // anyone could bind a socket in /tmp before us, so it goes into a directory only we can enter
+    let dir = PathBuf::from(format!("/tmp/ministatus-{}", unsafe { libc::getuid() }));
+    private_dir(&dir)?;
+    Ok(dir.join("ministatus.sock"))
+}
+
+/// Creates `dir` accessible only by us, or makes sure it is if it exists already
+fn private_dir(dir: &Path) -> Result<(), anyhow::Error> {
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e.into()),
+        _ => (),
+    }
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() || meta.uid() != unsafe { libc::getuid() } || meta.mode() & 0o077 != 0 {
+        return Err(anyhow::anyhow!(
+            "{} is not a directory only we can access",
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Sends `args` to the running instance and waits for its answer
+pub fn send(args: &[String]) -> Result<(), anyhow::Error> {
+    let mut stream = UnixStream::connect(path()?)?;
+    writeln!(stream, "{}", args.join(" "))?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    match reply.trim_end() {
+        "ok" => Ok(()),
+        e => Err(anyhow::anyhow!(
+            "{}",
+            e.strip_prefix("error: ").unwrap_or(e)
+        )),
+    }
+}
+
+/// Whether another instance is listening already
+pub fn running() -> bool {
+    path().is_ok_and(|p| UnixStream::connect(p).is_ok())
+}
+
+/// Accepts commands in the background, the main loop is woken up through `redraw` to handle them
+pub fn listen(
+    requests: mpsc::Sender<Request>,
+    redraw: mpsc::Sender<()>,
+) -> Result<(), anyhow::Error> {
+    let path = path()?;
+    // left behind by an instance that did not exit cleanly
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // a client that never finishes its line must not block everyone else
+            let mut line = String::new();
+            if stream.set_read_timeout(Some(TIMEOUT)).is_err()
+                || BufReader::new(&stream).read_line(&mut line).is_err()
+            {
+                continue;
+            }
+            // another instance checking whether we are running
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (tx, rx) = mpsc::channel();
+            let request = Request {
+                args: line.split_whitespace().map(String::from).collect(),
+                reply: tx,
+            };
+            if requests.send(request).is_err() {
+                break;
+            }
+            let _ = redraw.send(());
+
+            let reply = match rx.recv_timeout(TIMEOUT) {
+                Ok(Ok(())) => "ok".into(),
+                Ok(Err(e)) => format!("error: {e}"),
+                Err(_) => "error: timed out".into(),
+            };
+            let _ = writeln!(&stream, "{reply}");
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn private_dir_permissions() {
+        let dir = std::env::temp_dir().join(format!("ministatus-ipc-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        private_dir(&dir).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        private_dir(&dir).unwrap();
+
+        // created by someone else or opened up later
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(private_dir(&dir).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,36 +2,54 @@
 #![deny(clippy::pedantic)]
 
 use std::collections::HashMap;
+use std::sync::mpsc::Sender;
 
 use crate::block::Block;
 
 mod block;
+mod inotify;
+mod ipc;
+mod netlink;
+mod notify;
 mod shared;
 mod xorg;
 
 fn main() -> Result<(), anyhow::Error> {
+    // `ministatus <block> <args...>` sends a command to the running instance, e.g. `pulse up`
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        return ipc::send(&args);
+    }
+    if ipc::running() {
+        return Err(anyhow::anyhow!("ministatus is already running"));
+    }
+
     let window = xorg::Window::new();
     let home = std::env::var("HOME")?;
 
-    let mut blocks: Vec<Box<dyn Block>> = Vec::new();
-    match block::News::new(&home) {
-        Ok(v) => blocks.push(Box::new(v)),
-        Err(e) => eprintln!("news disabled because of {e}"),
-    }
-    match block::Mailbox::new(&home) {
-        Ok(v) => blocks.push(Box::new(v)),
-        Err(e) => eprintln!("mailbox disabled because of {e}"),
+    // blocks that learn about changes on their own use this to redraw before the next tick
+    let (redraw, redraw_rx) = std::sync::mpsc::channel::<()>();
+
+    let blocks = blocks(&home, &redraw)?;
+
+    let (requests, requests_rx) = std::sync::mpsc::channel::<ipc::Request>();
+    if let Err(e) = ipc::listen(requests, redraw.clone()) {
+        eprintln!("commands disabled because of {e}");
     }
-    blocks.push(Box::new(block::Weather::new()));
-    blocks.push(Box::new(block::Internet::new()));
-    blocks.push(Box::new(block::Battery::new()));
-    blocks.push(Box::new(block::Pulse::new()?));
-    blocks.push(Box::new(block::Clock::new()));
 
     let mut prev_state: HashMap<usize, String> = HashMap::new();
     let debug = std::env::var("DEBUG").is_ok_and(|v| v == "1");
 
     loop {
+        while let Ok(req) = requests_rx.try_recv() {
+            let args = req.args.iter().map(String::as_str).collect::<Vec<_>>();
+            let res = blocks
+                .iter()
+                .find_map(|b| b.command(&args))
+                .unwrap_or_else(|| Err(anyhow::anyhow!("unknown command")));
+            let _ = req.reply.send(res.map_err(|e| e.to_string()));
+        }
+
         let now = std::time::Instant::now();
         let mut out: Vec<String> = vec![];
         for (i, m) in blocks.iter().enumerate() {
@@ -56,6 +74,90 @@ fn main() -> Result<(), anyhow::Error> {
         } else if let Err(e) = window.set_title(&text) {
             eprintln!("failed to write to window: {e}");
         }
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        let _ = redraw_rx.recv_timeout(std::time::Duration::from_secs(1));
+        // a burst of changes, e.g. dragging a volume slider, only needs one redraw
+        while redraw_rx.try_recv().is_ok() {}
     }
 }
+
+/// The blocks shown in the bar, from left to right
+fn blocks(home: &str, redraw: &Sender<()>) -> Result<Vec<Box<dyn Block>>, anyhow::Error> {
+    let mut blocks: Vec<Box<dyn Block>> = Vec::new();
+    match block::News::new(home) {
+        Ok(v) => blocks.push(Box::new(v)),
+        Err(e) => eprintln!("news disabled because of {e}"),
+    }
+    // local delivery like cron output ends up in the spool
+    let spool = format!("/var/mail/{}", std::env::var("USER").unwrap_or_default());
+    let mailbox = block::Mailbox::new(home, redraw.clone())
+        .roots(&[".local/share/mail", "Mail"])
+        .mbox(&[&spool])
+        .notmuch(&[
+            ("inbox", "tag:inbox and tag:unread"),
+            ("flagged", "tag:flagged"),
+        ])
+        .folders(&["INBOX", "Inbox"])
+        .group(block::MailGroup::Account)
+        .unread(true)
+        .flagged(true)
+        .exclude(&["Spam", "Junk", "*/Trash"])
+        .and_then(block::Mailbox::build);
+    match mailbox {
+        Ok(v) => blocks.push(Box::new(v)),
+        Err(e) => eprintln!("mailbox disabled because of {e}"),
+    }
+    blocks.push(Box::new(block::Weather::new()));
+    let mut internet = block::Internet::new(redraw.clone())
+        .ignore(&["docker*", "veth*", "virbr*"])?
+        .format("{icon} {ssid} {quality}% {ipv4}");
+    // nothing is sent anywhere unless asked to, e.g.
+    // `MINISTATUS_PROBE=http://connectivitycheck.gstatic.com/generate_204` or `1.1.1.1:443`
+    if let Ok(target) = std::env::var("MINISTATUS_PROBE") {
+        internet = internet.probe(
+            block::Probe::new(&target),
+            std::time::Duration::from_mins(1),
+        );
+    }
+    blocks.push(Box::new(internet));
+    blocks.push(Box::new(
+        block::Vpn::new().stale_after(std::time::Duration::from_mins(5)),
+    ));
+    let mut battery = block::Battery::new(redraw.clone())
+        .aggregate()
+        .detail(true)
+        .health(true)
+        .notify(&[20, 10, 5]);
+    // only if asked to, e.g. `MINISTATUS_BATTERY_COMMAND="systemctl suspend"`
+    if let Ok(command) = std::env::var("MINISTATUS_BATTERY_COMMAND") {
+        battery = battery.command_below(3, &command.split_whitespace().collect::<Vec<_>>());
+    }
+    blocks.push(Box::new(battery));
+    blocks.push(Box::new(block::PeripheralBattery::new().low(15)));
+    blocks.push(Box::new(
+        block::Pulse::new(redraw.clone())
+            .microphone(true)
+            .recording(true)
+            .step(5)
+            .icons(&[
+                ("headphone", "🎧"),
+                ("headset", "🎧"),
+                ("bluetooth", "ᛒ"),
+                ("hdmi", "📺"),
+            ])
+            .device(true)
+            .channels(true)
+            .max_volume(150)
+            .fallback(
+                block::Alsa::new(redraw.clone())
+                    .card(0)
+                    .control("Master")
+                    .step(5),
+            ),
+    ));
+    match block::Media::new(redraw.clone(), &["spotify", "mpd"]) {
+        Ok(v) => blocks.push(Box::new(v.max_width(40))),
+        Err(e) => eprintln!("media disabled because of {e}"),
+    }
+    blocks.push(Box::new(block::Clock::new()));
+    Ok(blocks)
+}
@@ -0,0 +1,317 @@
+use std::cell::Cell;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::Duration;
+
+const NLMSG_HDRLEN: usize = 16;
+const NLA_HDRLEN: usize = 4;
+const GENL_HDRLEN: usize = 4;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+pub const NLM_F_REQUEST: u16 = 0x01;
+pub const NLM_F_ACK: u16 = 0x04;
+pub const NLM_F_DUMP: u16 = 0x300;
+
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+
+const RECV_BUF: usize = 64 * 1024;
+// requests are made on the main thread, a lost reply must not freeze the bar
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+const fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+#[derive(Debug)]
+pub struct Message {
+    pub ty: u16,
+    pub seq: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    /// Attributes of a generic netlink message, skipping the `genlmsghdr`
+    pub fn genl_attrs(&self) -> &[u8] {
+        self.payload.get(GENL_HDRLEN..).unwrap_or_default()
+    }
+}
+
+/// Minimal blocking netlink socket, just enough for rtnetlink, generic netlink and uevents
+pub struct Socket {
+    fd: OwnedFd,
+    seq: Cell<u32>,
+}
+
+impl Socket {
+    pub fn new(protocol: i32, groups: u32) -> Result<Self, anyhow::Error> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                protocol,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        {
+            addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        }
+        addr.nl_groups = groups;
+        #[allow(clippy::cast_possible_truncation)]
+        let r = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                (&raw const addr).cast(),
+                size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if r < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        // sockets without multicast groups are only used for requests, listeners block forever
+        if groups == 0 {
+            let timeout = libc::timeval {
+                tv_sec: libc::time_t::try_from(REQUEST_TIMEOUT.as_secs())?,
+                tv_usec: 0,
+            };
+            #[allow(clippy::cast_possible_truncation)]
+            let r = unsafe {
+                libc::setsockopt(
+                    fd.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_RCVTIMEO,
+                    (&raw const timeout).cast(),
+                    size_of::<libc::timeval>() as libc::socklen_t,
+                )
+            };
+            if r < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+
+        Ok(Self {
+            fd,
+            seq: Cell::new(0),
+        })
+    }
+
+    pub fn send(&self, ty: u16, flags: u16, payload: &[u8]) -> Result<u32, anyhow::Error> {
+        let seq = self.seq.get().wrapping_add(1);
+        self.seq.set(seq);
+
+        let len = NLMSG_HDRLEN + payload.len();
+        let mut buf = Vec::with_capacity(align(len));
+        buf.extend_from_slice(&u32::try_from(len)?.to_ne_bytes());
+        buf.extend_from_slice(&ty.to_ne_bytes());
+        buf.extend_from_slice(&flags.to_ne_bytes());
+        buf.extend_from_slice(&seq.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(payload);
+        buf.resize(align(len), 0);
+
+        let r = unsafe { libc::send(self.fd.as_raw_fd(), buf.as_ptr().cast(), buf.len(), 0) };
+        if r < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(seq)
+    }
+
+    /// Receives one datagram without interpreting it, used for uevents which are not netlink
+    /// messages
+    pub fn recv_raw(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut buf = vec![0u8; RECV_BUF];
+        let r = unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if r < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        buf.truncate(usize::try_from(r)?);
+        Ok(buf)
+    }
+
+    /// Receives one datagram and splits it into its netlink messages
+    pub fn recv(&self) -> Result<Vec<Message>, anyhow::Error> {
+        let buf = self.recv_raw()?;
+        let mut out = vec![];
+        let mut rest = &buf[..];
+        while rest.len() >= NLMSG_HDRLEN {
+            let len = usize::try_from(u32::from_ne_bytes(rest[0..4].try_into()?))?;
+            if len < NLMSG_HDRLEN || len > rest.len() {
+                break;
+            }
+            out.push(Message {
+                ty: u16::from_ne_bytes(rest[4..6].try_into()?),
+                seq: u32::from_ne_bytes(rest[8..12].try_into()?),
+                payload: rest[NLMSG_HDRLEN..len].to_vec(),
+            });
+            rest = &rest[align(len).min(rest.len())..];
+        }
+        Ok(out)
+    }
+
+    /// Sends a request and collects all replies until the kernel acknowledges it or finishes
+    /// the dump
+    pub fn request(
+        &self,
+        ty: u16,
+        flags: u16,
+        payload: &[u8],
+    ) -> Result<Vec<Message>, anyhow::Error> {
+        let flags = if flags & NLM_F_DUMP == 0 {
+            flags | NLM_F_REQUEST | NLM_F_ACK
+        } else {
+            flags | NLM_F_REQUEST
+        };
+        let seq = self.send(ty, flags, payload)?;
+
+        let mut out = vec![];
+        loop {
+            for msg in self.recv()? {
+                if msg.seq != seq {
+                    continue;
+                }
+                match msg.ty {
+                    NLMSG_DONE => return Ok(out),
+                    NLMSG_ERROR => {
+                        let code = msg
+                            .payload
+                            .get(..4)
+                            .map_or(0, |v| i32::from_ne_bytes(v.try_into().unwrap_or_default()));
+                        if code == 0 {
+                            return Ok(out);
+                        }
+                        return Err(std::io::Error::from_raw_os_error(-code).into());
+                    }
+                    _ => out.push(msg),
+                }
+            }
+        }
+    }
+
+    /// Same as `request` but prepends a `genlmsghdr` for the given command
+    pub fn genl_request(
+        &self,
+        family: u16,
+        cmd: u8,
+        flags: u16,
+        attrs: &[u8],
+    ) -> Result<Vec<Message>, anyhow::Error> {
+        let mut payload = vec![cmd, 1, 0, 0];
+        payload.extend_from_slice(attrs);
+        self.request(family, flags, &payload)
+    }
+
+    /// Resolves the id of a generic netlink family, e.g. `nl80211`
+    pub fn genl_family(&self, name: &str) -> Result<u16, anyhow::Error> {
+        let mut attrs = vec![];
+        let mut name = name.as_bytes().to_vec();
+        name.push(0);
+        push_attr(&mut attrs, CTRL_ATTR_FAMILY_NAME, &name);
+
+        self.genl_request(GENL_ID_CTRL, CTRL_CMD_GETFAMILY, 0, &attrs)?
+            .iter()
+            .find_map(|m| attr(m.genl_attrs(), CTRL_ATTR_FAMILY_ID).and_then(u16_attr))
+            .ok_or_else(|| anyhow::anyhow!("generic netlink family not found"))
+    }
+}
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Listens on a socket subscribed to `groups` in the background and hands every datagram to
+/// `handle`, or `None` if we missed some because the socket buffer overflowed. Redraws whenever
+/// `handle` returns true. `watching` tells whether events still arrive, the caller polls if not.
+pub fn listen(
+    protocol: i32,
+    groups: u32,
+    name: &'static str,
+    watching: &Arc<AtomicBool>,
+    redraw: mpsc::Sender<()>,
+    mut handle: impl FnMut(Option<&[u8]>) -> bool + Send + 'static,
+) {
+    let sock = match Socket::new(protocol, groups) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{name} not available, polling instead: {e}");
+            return;
+        }
+    };
+
+    // before the thread starts, so a failing thread can not be overruled
+    watching.store(true, Ordering::Relaxed);
+    let watching = watching.clone();
+    std::thread::spawn(move || {
+        loop {
+            let changed = match sock.recv_raw() {
+                Ok(buf) => handle(Some(&buf)),
+                Err(e)
+                    if e.downcast_ref::<std::io::Error>()
+                        .and_then(std::io::Error::raw_os_error)
+                        == Some(libc::ENOBUFS) =>
+                {
+                    handle(None)
+                }
+                Err(e) => {
+                    eprintln!("{name} stopped, polling instead: {e}");
+                    watching.store(false, Ordering::Relaxed);
+                    let _ = redraw.send(());
+                    break;
+                }
+            };
+            if changed && redraw.send(()).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+pub fn push_attr(buf: &mut Vec<u8>, ty: u16, data: &[u8]) {
+    let len = NLA_HDRLEN + data.len();
+    buf.extend_from_slice(&u16::try_from(len).unwrap_or(u16::MAX).to_ne_bytes());
+    buf.extend_from_slice(&ty.to_ne_bytes());
+    buf.extend_from_slice(data);
+    buf.resize(buf.len() + align(len) - len, 0);
+}
+
+/// Iterates over `(type, payload)` of all attributes in `buf`
+pub fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < NLA_HDRLEN {
+            return None;
+        }
+        let len = usize::from(u16::from_ne_bytes([buf[0], buf[1]]));
+        let ty = u16::from_ne_bytes([buf[2], buf[3]]) & NLA_TYPE_MASK;
+        if len < NLA_HDRLEN || len > buf.len() {
+            return None;
+        }
+        let data = &buf[NLA_HDRLEN..len];
+        buf = &buf[align(len).min(buf.len())..];
+        Some((ty, data))
+    })
+}
+
+pub fn attr(buf: &[u8], ty: u16) -> Option<&[u8]> {
+    attrs(buf).find(|(t, _)| *t == ty).map(|(_, d)| d)
+}
+
+pub fn u16_attr(data: &[u8]) -> Option<u16> {
+    Some(u16::from_ne_bytes(data.get(..2)?.try_into().ok()?))
+}
+
+pub fn u32_attr(data: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(data.get(..4)?.try_into().ok()?))
+}
@@ -0,0 +1,72 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use zbus::zvariant::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Normal = 1,
+    Critical = 2,
+}
+
+/// Something that shows notifications to the user, a `Notifier` unless under test
+pub trait Notify {
+    fn send(&self, summary: &str, body: &str, urgency: Urgency) -> Result<(), anyhow::Error>;
+}
+
+/// Desktop notifications over `org.freedesktop.Notifications` on the session bus. Every
+/// notification replaces the previous one of the same `Notifier`.
+pub struct Notifier {
+    conn: RefCell<Option<zbus::blocking::Connection>>,
+    id: Cell<u32>,
+}
+
+impl Notifier {
+    pub const fn new() -> Self {
+        Self {
+            conn: RefCell::new(None),
+            id: Cell::new(0),
+        }
+    }
+}
+
+impl Notify for Notifier {
+    fn send(&self, summary: &str, body: &str, urgency: Urgency) -> Result<(), anyhow::Error> {
+        let conn = if let Some(conn) = self.conn.borrow().as_ref() {
+            conn.clone()
+        } else {
+            let conn = zbus::blocking::Connection::session()?;
+            *self.conn.borrow_mut() = Some(conn.clone());
+            conn
+        };
+
+        let hints = HashMap::from([("urgency", Value::U8(urgency as u8))]);
+        let reply = conn.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "ministatus",
+                self.id.get(),
+                "",
+                summary,
+                body,
+                Vec::<&str>::new(),
+                hints,
+                -1i32,
+            ),
+        );
+        match reply {
+            Ok(reply) => {
+                self.id.set(reply.body().deserialize::<u32>()?);
+                Ok(())
+            }
+            Err(e) => {
+                // the bus might have gone away, reconnect next time
+                self.conn.borrow_mut().take();
+                Err(e.into())
+            }
+        }
+    }
+}